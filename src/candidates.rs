@@ -0,0 +1,73 @@
+use crate::case_combinations::{stream_case_modes, CaseMode};
+use crate::character_combinations::{stream_hybrid, CountConfig, LengthConfig, Mask};
+use crate::leet_combinations::{stream_leet, LeetTable};
+use crate::phrase_combinations::{stream_phrases, PhraseStyle};
+use crate::rules::{stream_rules, Rule};
+use crate::sanitize::{self, SanitizeConfig};
+
+/// the generators as iterator adapters, so stages compose in any order:
+/// `words.sanitized().leet(&table, None).lengths(&config)`
+pub trait Candidates: Iterator<Item = String> + Sized {
+    fn sanitized(self) -> impl Iterator<Item = String> {
+        self.flat_map(|word| sanitize::stream(&word))
+    }
+
+    fn sanitized_with(self, config: &SanitizeConfig) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| config.stream(&word))
+    }
+
+    fn phrases(self, styles: &[PhraseStyle]) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| stream_phrases(&word, styles))
+    }
+
+    fn cases(self, modes: &[CaseMode], max_changes: Option<usize>) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| stream_case_modes(&word, modes, max_changes))
+    }
+
+    fn leet(
+        self,
+        table: &LeetTable,
+        max_substitutions: Option<usize>,
+    ) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| stream_leet(&word, table, max_substitutions))
+    }
+
+    fn rules(self, rules: &[Rule]) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| stream_rules(&word, rules))
+    }
+
+    fn lengths(self, config: &LengthConfig) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| config.stream(&word))
+    }
+
+    fn counts(self, config: &CountConfig) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| config.stream(&word))
+    }
+
+    fn hybrid<'a>(self, prepend: &'a Mask, append: &'a Mask) -> impl Iterator<Item = String> {
+        self.flat_map(move |word| stream_hybrid(&word, prepend, append))
+    }
+}
+
+impl<I: Iterator<Item = String>> Candidates for I {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::leet_combinations::LeetPreset;
+
+    #[test]
+    fn stages_chain_over_words() {
+        let table = LeetTable::preset(LeetPreset::Basic);
+        let lengths = LengthConfig::new("1").min(1).max(4).append(true);
+        let words = ["be".to_string()].into_iter();
+        let out: Vec<String> = words
+            .cases(&[CaseMode::First], None)
+            .leet(&table, Some(1))
+            .lengths(&lengths)
+            .collect();
+        assert_eq!(out.len(), 2 * 3 * 3);
+        assert!(out.contains(&"B31".to_string()));
+        assert!(out.contains(&"8e11".to_string()));
+    }
+}
@@ -1,41 +1,206 @@
-/// gen combinations of case (upper/lower) transformations in the word
+use crate::error::Error;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// gen combinations of case (upper/lower) transformations in the word.
+/// only chars with a unicode case mapping vary, and every variant is distinct
 pub fn stream_cases(word: &str, max_changes: Option<usize>) -> impl Iterator<Item = String> {
     CasePermutations::new(word, max_changes)
 }
 
+/// count the variants `stream_cases` would produce without generating them
+pub fn case_keyspace(word: &str, max_changes: Option<usize>) -> u128 {
+    let choices = case_choices(word);
+    let changes = choices.iter().map(|options| (1, options.len() - 1));
+    count_within_limit(changes, max_changes)
+}
+
+/// per char, the original followed by its distinct lower and upper forms.
+/// mappings can expand (`ß` uppercases to `SS`), so forms are strings
+fn case_choices(word: &str) -> Vec<Vec<String>> {
+    word.chars()
+        .map(|ch| {
+            let mut options = vec![ch.to_string()];
+            for form in [ch.to_lowercase().collect(), ch.to_uppercase().collect()] {
+                if !options.contains(&form) {
+                    options.push(form);
+                }
+            }
+            options
+        })
+        .collect()
+}
+
+/// case strategies. `full` is every permutation `stream_cases` produces, the
+/// others each add at most one variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaseMode {
+    First,
+    Upper,
+    Lower,
+    Toggle,
+    Title,
+    Last,
+    Full,
+}
+
+impl FromStr for CaseMode {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "first" => Ok(CaseMode::First),
+            "upper" => Ok(CaseMode::Upper),
+            "lower" => Ok(CaseMode::Lower),
+            "toggle" => Ok(CaseMode::Toggle),
+            "title" => Ok(CaseMode::Title),
+            "last" => Ok(CaseMode::Last),
+            "full" => Ok(CaseMode::Full),
+            _ => Err(Error::UnknownName {
+                kind: "case mode",
+                name: name.to_string(),
+                expected: "first, upper, lower, toggle, title, last or full",
+            }),
+        }
+    }
+}
+
+impl CaseMode {
+    /// the variant this mode makes and how many chars it changed
+    fn apply(self, chars: &[char]) -> (String, usize) {
+        let cased: Vec<usize> = (0..chars.len())
+            .filter(|&idx| chars[idx].is_lowercase() || chars[idx].is_uppercase())
+            .collect();
+        let mut out = String::with_capacity(chars.len());
+        let mut changes = 0;
+        for (idx, &ch) in chars.iter().enumerate() {
+            let upper = match self {
+                CaseMode::First => cased.first() == Some(&idx),
+                CaseMode::Last => cased.last() == Some(&idx),
+                CaseMode::Title => idx == 0 || !chars[idx - 1].is_alphabetic(),
+                CaseMode::Upper => true,
+                CaseMode::Toggle => !ch.is_uppercase(),
+                CaseMode::Lower | CaseMode::Full => false,
+            };
+            let form: String = if upper {
+                ch.to_uppercase().collect()
+            } else {
+                ch.to_lowercase().collect()
+            };
+            if form.chars().ne(std::iter::once(ch)) {
+                changes += 1;
+            }
+            out.push_str(&form);
+        }
+        (out, changes)
+    }
+}
+
+/// the word followed by the distinct variants of each mode. with `full` among
+/// the modes this is exactly `stream_cases`
+pub fn stream_case_modes(
+    word: &str,
+    modes: &[CaseMode],
+    max_changes: Option<usize>,
+) -> impl Iterator<Item = String> {
+    let full = modes.contains(&CaseMode::Full);
+    let targeted = if full {
+        Vec::new()
+    } else {
+        targeted_variants(word, modes, max_changes)
+    };
+    full.then(|| stream_cases(word, max_changes))
+        .into_iter()
+        .flatten()
+        .chain(targeted)
+}
+
+/// count the variants `stream_case_modes` would produce
+pub fn case_modes_keyspace(word: &str, modes: &[CaseMode], max_changes: Option<usize>) -> u128 {
+    if modes.contains(&CaseMode::Full) {
+        case_keyspace(word, max_changes)
+    } else {
+        targeted_variants(word, modes, max_changes).len() as u128
+    }
+}
+
+fn targeted_variants(word: &str, modes: &[CaseMode], max_changes: Option<usize>) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut out = vec![word.to_string()];
+    for mode in modes {
+        let (variant, changes) = mode.apply(&chars);
+        let allowed = max_changes.is_none_or(|limit| changes <= limit);
+        if allowed && !out.contains(&variant) {
+            out.push(variant);
+        }
+    }
+    out
+}
+
+/// `by_changes[k]` holds how many states change exactly `k` chars. each item
+/// gives how many of a char's options keep it and how many change it
+fn count_within_limit<I>(choices: I, max_changes: Option<usize>) -> u128
+where
+    I: Iterator<Item = (usize, usize)>,
+{
+    let mut by_changes: Vec<u128> = vec![1];
+    for (keep, change) in choices {
+        let mut next = vec![0u128; by_changes.len() + 1];
+        for (changes, &count) in by_changes.iter().enumerate() {
+            next[changes] = next[changes].saturating_add(count.saturating_mul(keep as u128));
+            next[changes + 1] =
+                next[changes + 1].saturating_add(count.saturating_mul(change as u128));
+        }
+        if let Some(limit) = max_changes {
+            next.truncate(limit + 1);
+        }
+        by_changes = next;
+    }
+    by_changes.into_iter().fold(0u128, u128::saturating_add)
+}
+
 struct CasePermutations {
-    chars: Vec<char>,
-    state: Vec<u8>,
+    options: Vec<Vec<String>>,
+    /// positions with more than one form, the only ones the odometer turns
+    varying: Vec<usize>,
+    state: Vec<usize>,
     finished: bool,
+    /// forms of one char can prefix each other, letting two states spell the same string
+    seen: Option<HashSet<String>>,
     max_changes: Option<usize>,
 }
 
 impl CasePermutations {
     fn new(word: &str, max_changes: Option<usize>) -> Self {
-        let chars: Vec<char> = word.chars().collect();
-        let state = vec![0; chars.len()];
+        let options = case_choices(word);
+        let varying: Vec<usize> = (0..options.len())
+            .filter(|&idx| options[idx].len() > 1)
+            .collect();
+        let ambiguous = options.iter().any(|forms| {
+            forms.iter().enumerate().any(|(a, form)| {
+                forms
+                    .iter()
+                    .enumerate()
+                    .any(|(b, other)| a != b && other.starts_with(form.as_str()))
+            })
+        });
         Self {
-            chars,
-            state,
+            state: vec![0; options.len()],
+            options,
+            varying,
             finished: false,
+            seen: ambiguous.then(HashSet::new),
             max_changes,
         }
     }
 
     fn advance(&mut self) {
-        if self.state.is_empty() {
-            self.finished = true;
-            return;
-        }
-
-        for idx in (0..self.state.len()).rev() {
-            if self.state[idx] == 0 {
-                self.state[idx] = 1;
-                for reset in idx + 1..self.state.len() {
-                    self.state[reset] = 0;
-                }
+        for &idx in self.varying.iter().rev() {
+            self.state[idx] += 1;
+            if self.state[idx] < self.options[idx].len() {
                 return;
             }
+            self.state[idx] = 0;
         }
         self.finished = true;
     }
@@ -46,27 +211,25 @@ impl Iterator for CasePermutations {
 
     fn next(&mut self) -> Option<Self::Item> {
         while !self.finished {
-            let mut out = String::with_capacity(self.chars.len());
+            let mut out = String::with_capacity(self.options.len());
             let mut changes = 0usize;
-            for (idx, ch) in self.chars.iter().enumerate() {
-                let upper = self.state.get(idx).copied().unwrap_or(0) == 1;
-                let transformed = if upper {
-                    ch.to_ascii_uppercase()
-                } else {
-                    ch.to_ascii_lowercase()
-                };
-                if transformed != *ch {
+            for (forms, &choice) in self.options.iter().zip(&self.state) {
+                if choice > 0 {
                     changes += 1;
                 }
-                out.push(transformed);
+                out.push_str(&forms[choice]);
             }
-            let allowed = self
-                .max_changes
-                .map_or(true, |limit| changes <= limit);
+            let allowed = self.max_changes.is_none_or(|limit| changes <= limit);
             self.advance();
-            if allowed {
-                return Some(out);
+            if !allowed {
+                continue;
             }
+            if let Some(seen) = self.seen.as_mut() {
+                if !seen.insert(out.clone()) {
+                    continue;
+                }
+            }
+            return Some(out);
         }
         None
     }
@@ -92,6 +255,58 @@ mod tests {
         assert_eq!(variants, vec!["".to_string()]);
     }
 
+    #[test]
+    fn counts_match_generated_variants() {
+        let cases = [
+            ("ab", None),
+            ("a1b", None),
+            ("AbC", Some(1)),
+            ("", None),
+            ("stra\u{df}e", None),
+            ("\u{130}x", Some(1)),
+        ];
+        for (word, limit) in cases {
+            let generated = stream_cases(word, limit).count() as u128;
+            assert_eq!(case_keyspace(word, limit), generated, "{word}");
+        }
+    }
+
+    #[test]
+    fn skips_caseless_chars_and_maps_unicode() {
+        let variants: Vec<String> = stream_cases("abc123", None).collect();
+        assert_eq!(variants.len(), 8);
+        let variants: Vec<String> = stream_cases("\u{e9}\u{df}", None).collect();
+        assert_eq!(
+            variants,
+            vec!["\u{e9}\u{df}", "\u{e9}SS", "\u{c9}\u{df}", "\u{c9}SS"]
+        );
+    }
+
+    #[test]
+    fn applies_targeted_case_modes() {
+        let modes: Vec<CaseMode> = "first,title,upper,toggle,last"
+            .split(',')
+            .map(|name| name.parse().unwrap())
+            .collect();
+        let variants: Vec<String> = stream_case_modes("hello World1", &modes, None).collect();
+        assert_eq!(
+            variants,
+            vec![
+                "hello World1",
+                "Hello world1",
+                "Hello World1",
+                "HELLO WORLD1",
+                "HELLO wORLD1",
+                "hello worlD1",
+            ]
+        );
+        let limited: Vec<String> = stream_case_modes("abc", &modes, Some(1)).collect();
+        assert_eq!(limited, vec!["abc", "Abc", "abC"]);
+        assert_eq!(case_modes_keyspace("abc", &modes, Some(1)), 3);
+        let full = stream_case_modes("abc", &[CaseMode::First, CaseMode::Full], None);
+        assert_eq!(full.count(), 8);
+    }
+
     #[test]
     fn respects_case_change_limit() {
         let variants: Vec<String> = stream_cases("abc", Some(1)).collect();
@@ -1,33 +1,140 @@
-use std::collections::VecDeque;
-
-pub fn stream_count(
-    word: &str,
-    chars: &str,
-    cnt_append: usize,
-    cnt_prepend: usize,
-    cnt_insert: usize,
-) -> impl Iterator<Item = String> {
-    CountIterator::new(word, chars, cnt_append, cnt_prepend, cnt_insert)
+use std::collections::{HashMap, VecDeque};
+
+use super::unit::LengthUnit;
+
+/// add exactly up to `append`, `prepend` and `insert` charset chars to words,
+/// yielding every intermediate candidate too. inserted chars go between the
+/// chars of the word, or between its graphemes when `unit` counts those
+#[derive(Clone, Debug)]
+pub struct CountConfig {
+    chars: String,
+    append: usize,
+    prepend: usize,
+    insert: usize,
+    unit: LengthUnit,
+}
+
+impl CountConfig {
+    pub fn new(chars: impl Into<String>) -> Self {
+        Self {
+            chars: chars.into(),
+            append: 0,
+            prepend: 0,
+            insert: 0,
+            unit: LengthUnit::default(),
+        }
+    }
+
+    pub fn chars(&self) -> &str {
+        &self.chars
+    }
+
+    pub fn append(mut self, append: usize) -> Self {
+        self.append = append;
+        self
+    }
+
+    pub fn prepend(mut self, prepend: usize) -> Self {
+        self.prepend = prepend;
+        self
+    }
+
+    pub fn insert(mut self, insert: usize) -> Self {
+        self.insert = insert;
+        self
+    }
+
+    pub fn unit(mut self, unit: LengthUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn stream(&self, word: &str) -> impl Iterator<Item = String> {
+        CountIterator::new(word, self)
+    }
+
+    /// count the variants `stream` would produce, as `(length, count)` pairs.
+    /// added chars are assumed to share the charset's average byte width
+    pub fn keyspace(&self, word: &str) -> Vec<(usize, u128)> {
+        let charset_len = self.chars.chars().count();
+        let width = self
+            .chars
+            .len()
+            .checked_div(charset_len)
+            .unwrap_or(1)
+            .max(1);
+        let budget = (self.append, self.prepend, self.insert);
+        let mut memo = HashMap::new();
+        // the gaps between the word's chars or graphemes, not its bytes
+        let gaps = self.unit.insert_positions(word).len() - 1;
+        let per_depth = nodes_by_depth(gaps, charset_len as u128, budget, budget, &mut memo);
+        per_depth
+            .into_iter()
+            .enumerate()
+            .map(|(added, count)| (word.len() + added * width, count))
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+}
+
+type Remaining = (usize, usize, usize);
+
+/// every queued entry is emitted once, so the keyspace is the size of the bfs tree
+fn nodes_by_depth(
+    word_len: usize,
+    charset_len: u128,
+    initial: Remaining,
+    remaining: Remaining,
+    memo: &mut HashMap<Remaining, Vec<u128>>,
+) -> Vec<u128> {
+    if let Some(known) = memo.get(&remaining) {
+        return known.clone();
+    }
+    let (a, p, i) = remaining;
+    let current_len = word_len + (initial.0 - a) + (initial.1 - p) + (initial.2 - i);
+    let mut children: Vec<(Remaining, u128)> = Vec::new();
+    if a > 0 {
+        children.push(((a - 1, p, i), charset_len));
+    }
+    if p > 0 {
+        children.push(((a, p - 1, i), charset_len));
+    }
+    if i > 0 {
+        children.push(((a, p, i - 1), charset_len * (current_len as u128 + 1)));
+    }
+    let mut out = vec![1u128];
+    for (child, fanout) in children {
+        let below = nodes_by_depth(word_len, charset_len, initial, child, memo);
+        if out.len() < below.len() + 1 {
+            out.resize(below.len() + 1, 0);
+        }
+        for (depth, count) in below.into_iter().enumerate() {
+            out[depth + 1] = out[depth + 1].saturating_add(count.saturating_mul(fanout));
+        }
+    }
+    memo.insert(remaining, out.clone());
+    out
 }
 
 struct CountIterator {
     queue: VecDeque<(String, usize, usize, usize)>,
     chars: Vec<char>,
+    unit: LengthUnit,
 }
 
 impl CountIterator {
-    fn new(
-        word: &str,
-        chars: &str,
-        cnt_append: usize,
-        cnt_prepend: usize,
-        cnt_insert: usize,
-    ) -> Self {
+    fn new(word: &str, config: &CountConfig) -> Self {
         let mut queue = VecDeque::new();
-        queue.push_back((word.to_string(), cnt_append, cnt_prepend, cnt_insert));
+        queue.push_back((
+            word.to_string(),
+            config.append,
+            config.prepend,
+            config.insert,
+        ));
         Self {
             queue,
-            chars: chars.chars().collect(),
+            chars: config.chars.chars().collect(),
+            unit: config.unit,
         }
     }
 }
@@ -58,7 +165,7 @@ impl Iterator for CountIterator {
             if i > 0 {
                 for idx in 0..self.chars.len() {
                     let ch = self.chars[idx];
-                    for pos in 0..=current.len() {
+                    for pos in self.unit.insert_positions(&current) {
                         let mut cand = current.clone();
                         cand.insert(pos, ch);
                         self.queue.push_back((cand, a, p, i - 1));
@@ -77,10 +184,44 @@ mod tests {
 
     #[test]
     fn count_iterator_runs_all_operations() {
-        let generated: Vec<String> = stream_count("a", "b", 1, 1, 0).collect();
+        let config = CountConfig::new("b").append(1).prepend(1);
+        let generated: Vec<String> = config.stream("a").collect();
         let set: HashSet<String> = generated.iter().cloned().collect();
         assert!(set.contains("a"));
         assert!(set.contains("ab"));
         assert!(set.contains("ba"));
     }
+
+    #[test]
+    fn keyspace_matches_generated_variants() {
+        for (a, p, i) in [(1, 1, 0), (2, 0, 1), (0, 1, 2), (0, 0, 0)] {
+            let config = CountConfig::new("xyz").append(a).prepend(p).insert(i);
+            let generated = config.stream("ab").count() as u128;
+            let estimated: u128 = config
+                .keyspace("ab")
+                .into_iter()
+                .map(|(_, count)| count)
+                .sum();
+            assert_eq!(estimated, generated, "{a} {p} {i}");
+        }
+    }
+
+    #[test]
+    fn inserts_between_chars_of_non_ascii_words() {
+        let config = CountConfig::new("1").insert(1);
+        let generated: Vec<String> = config.stream("né").collect();
+        assert_eq!(generated, vec!["né", "1né", "n1é", "né1"]);
+        let estimated: u128 = config
+            .keyspace("né")
+            .into_iter()
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(estimated, 4);
+        let config = config.unit(LengthUnit::Graphemes);
+        let generated: Vec<String> = config.stream("ne\u{301}").collect();
+        assert_eq!(
+            generated,
+            vec!["ne\u{301}", "1ne\u{301}", "n1e\u{301}", "ne\u{301}1"]
+        );
+    }
 }
@@ -0,0 +1,49 @@
+use super::mask::Mask;
+
+/// hashcat's hybrid modes: every `prepend` candidate, the word, then every
+/// `append` candidate. an empty mask stands for nothing on that side
+pub fn stream_hybrid<'a>(
+    word: &str,
+    prepend: &'a Mask,
+    append: &'a Mask,
+) -> impl Iterator<Item = String> + 'a {
+    let word = word.to_string();
+    prepend.candidates(0, u128::MAX).flat_map(move |prefix| {
+        let word = word.clone();
+        append
+            .candidates(0, u128::MAX)
+            .map(move |suffix| format!("{prefix}{word}{suffix}"))
+    })
+}
+
+/// candidates and bytes (newlines excluded) `stream_hybrid` would produce
+pub fn hybrid_keyspace(word: &str, prepend: &Mask, append: &Mask) -> (u128, u128) {
+    let (before, after) = (prepend.keyspace(), append.keyspace());
+    let candidates = before.saturating_mul(after);
+    let bytes = candidates
+        .saturating_mul(word.len() as u128)
+        .saturating_add(prepend.keyspace_bytes().saturating_mul(after))
+        .saturating_add(append.keyspace_bytes().saturating_mul(before));
+    (candidates, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_words_in_mask_candidates() {
+        let append = Mask::parse("?d?1", &[Some("!\u{e9}".to_string())]).unwrap();
+        let prepend = Mask::parse("?h", &[]).unwrap();
+        let variants: Vec<String> = stream_hybrid("pw", &Mask::default(), &append).collect();
+        assert_eq!(variants.len(), 20);
+        assert_eq!(variants[..2], ["pw0!", "pw0\u{e9}"]);
+        let both: Vec<String> = stream_hybrid("pw", &prepend, &append).collect();
+        let bytes: usize = both.iter().map(String::len).sum();
+        assert_eq!(
+            hybrid_keyspace("pw", &prepend, &append),
+            (320, bytes as u128)
+        );
+        assert_eq!(both[20], "1pw0!");
+    }
+}
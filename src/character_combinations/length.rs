@@ -1,114 +1,528 @@
 use std::collections::{HashSet, VecDeque};
+use std::io;
 
-pub fn stream_length(
-    word: &str,
-    chars: &str,
+use super::spill::{SpillConfig, SpillFrontier};
+use super::unit::LengthUnit;
+use crate::error::{Error, Result};
+
+/// grow words with charset chars (appended, prepended and/or inserted) up to
+/// `max` long, keeping every candidate at least `min` long. lengths are
+/// measured in `unit`s
+#[derive(Clone, Debug)]
+pub struct LengthConfig {
+    chars: String,
     min: usize,
     max: usize,
-    do_append: bool,
-    do_prepend: bool,
-    do_insert: bool,
+    unit: LengthUnit,
+    append: bool,
+    prepend: bool,
+    insert: bool,
     dedup: bool,
-) -> impl Iterator<Item = String> {
-    LengthIterator::new(word, chars, min, max, do_append, do_prepend, do_insert, dedup)
+}
+
+impl LengthConfig {
+    /// 2 to 16 chars, no mode enabled, duplicates dropped
+    pub fn new(chars: impl Into<String>) -> Self {
+        Self {
+            chars: chars.into(),
+            min: 2,
+            max: 16,
+            unit: LengthUnit::default(),
+            append: false,
+            prepend: false,
+            insert: false,
+            dedup: true,
+        }
+    }
+
+    pub fn chars(&self) -> &str {
+        &self.chars
+    }
+
+    pub fn min(mut self, min: usize) -> Self {
+        self.min = min;
+        self
+    }
+
+    pub fn max(mut self, max: usize) -> Self {
+        self.max = max;
+        self
+    }
+
+    pub fn unit(mut self, unit: LengthUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    pub fn prepend(mut self, prepend: bool) -> Self {
+        self.prepend = prepend;
+        self
+    }
+
+    pub fn insert(mut self, insert: bool) -> Self {
+        self.insert = insert;
+        self
+    }
+
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// the bfs frontier is kept in memory, which can't fail
+    pub fn stream(&self, word: &str) -> impl Iterator<Item = String> {
+        LengthIterator::new(word, self, None).map_while(|variant| variant.ok())
+    }
+
+    /// same variants as `stream`, with the bfs frontier kept in sorted temp
+    /// files past the spill threshold. stops at the first temp file error
+    pub fn stream_spilled(
+        &self,
+        word: &str,
+        spill: &SpillConfig,
+    ) -> impl Iterator<Item = Result<String>> {
+        LengthIterator::new(word, self, Some(spill.clone()))
+            .map(|variant| variant.map_err(Error::Spill))
+    }
+
+    /// count the variants `stream` would produce, as `(byte length, count)`
+    /// pairs. added chars are assumed to share the charset's average byte width
+    pub fn keyspace(&self, word: &str) -> Vec<(usize, u128)> {
+        let word_len = self.unit.measure(word);
+        if word_len > self.max {
+            return Vec::new();
+        }
+        let charset: Vec<char> = self.chars.chars().collect();
+        let expanding = !charset.is_empty() && (self.append || self.prepend || self.insert);
+        let width = self
+            .chars
+            .len()
+            .checked_div(charset.len())
+            .unwrap_or(1)
+            .max(1);
+        let added_width = self.unit.added_width(width);
+        let depth = if expanding { (self.max - word_len) / added_width } else { 0 };
+        let (append, prepend, insert) = (self.append, self.prepend, self.insert);
+        let per_depth = if self.dedup {
+            let symbols = self.unit.symbols(word);
+            distinct_by_depth(&symbols, &charset, depth, append, prepend, insert)
+        } else {
+            let positions = self.unit.insert_positions(word).len();
+            paths_by_depth(positions, charset.len(), depth, append, prepend, insert)
+        };
+        per_depth
+            .into_iter()
+            .enumerate()
+            .filter(|(added, count)| {
+                let len = word_len + added * added_width;
+                *count > 0 && len >= self.min && len <= self.max
+            })
+            .map(|(added, count)| (word.len() + added * width, count))
+            .collect()
+    }
 }
 
 struct LengthIterator {
     queue: VecDeque<String>,
     seen: Option<HashSet<String>>,
+    spill: Option<SpillFrontier>,
     chars: Vec<char>,
     min: usize,
     max: usize,
+    unit: LengthUnit,
     do_append: bool,
     do_prepend: bool,
     do_insert: bool,
 }
 
-impl LengthIterator {
-    fn new(
-        word: &str,
-        chars: &str,
-        min: usize,
-        max: usize,
-        do_append: bool,
-        do_prepend: bool,
-        do_insert: bool,
-        dedup: bool,
-    ) -> Self {
-        let mut queue = VecDeque::new();
-        let seen = if dedup {
-            let mut set = HashSet::new();
-            if word.len() <= max {
-                set.insert(word.to_string());
-                queue.push_back(word.to_string());
+/// every queued candidate is expanded, so without dedup the count is the number
+/// of paths. `positions` is where the word itself takes insertions
+fn paths_by_depth(
+    positions: usize,
+    charset_len: usize,
+    depth: usize,
+    do_append: bool,
+    do_prepend: bool,
+    do_insert: bool,
+) -> Vec<u128> {
+    let mut out = vec![1u128];
+    for added in 0..depth {
+        let slots =
+            do_append as usize + do_prepend as usize + do_insert as usize * (positions + added);
+        let fanout = (charset_len * slots) as u128;
+        let prev = out[added];
+        out.push(prev.saturating_mul(fanout));
+    }
+    out
+}
+
+/// `word` is split into the units chars are inserted between, so a grapheme
+/// of several chars counts as one symbol
+fn distinct_by_depth(
+    word: &[&str],
+    charset: &[char],
+    depth: usize,
+    do_append: bool,
+    do_prepend: bool,
+    do_insert: bool,
+) -> Vec<u128> {
+    let mut alphabet: Vec<String> = charset.iter().map(char::to_string).collect();
+    alphabet.sort_unstable();
+    alphabet.dedup();
+    let alphabet: Vec<&str> = alphabet.iter().map(String::as_str).collect();
+    let q = alphabet.len() as u128;
+    (0..=depth)
+        .map(|added| {
+            if added == 0 {
+                1
+            } else if do_insert {
+                count_supersequences(word, &alphabet, added)
+            } else if do_append && do_prepend {
+                if word.iter().all(|c| alphabet.contains(c)) {
+                    count_containing(word, &alphabet, word.len() + added)
+                } else {
+                    q.saturating_pow(added as u32)
+                        .saturating_mul(added as u128 + 1)
+                }
+            } else {
+                q.saturating_pow(added as u32)
+            }
+        })
+        .collect()
+}
+
+/// strings made by inserting `added` charset chars into `word`. a string
+/// qualifies iff its leftmost embedding of `word` leaves only charset chars over
+fn count_supersequences<T: PartialEq>(word: &[T], alphabet: &[T], added: usize) -> u128 {
+    let q = alphabet.len() as u128;
+    let mut states = vec![0u128; word.len() + 1];
+    states[0] = 1;
+    for _ in 0..word.len() + added {
+        let mut next = vec![0u128; word.len() + 1];
+        for (matched, &count) in states.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            if matched == word.len() {
+                next[matched] = next[matched].saturating_add(count.saturating_mul(q));
+                continue;
             }
-            Some(set)
+            let stay = q - alphabet.contains(&word[matched]) as u128;
+            next[matched] = next[matched].saturating_add(count.saturating_mul(stay));
+            next[matched + 1] = next[matched + 1].saturating_add(count);
+        }
+        states = next;
+    }
+    states[word.len()]
+}
+
+/// strings of `len` charset chars that contain `word` as a substring (kmp automaton)
+fn count_containing<T: PartialEq>(word: &[T], alphabet: &[T], len: usize) -> u128 {
+    let mut failure = vec![0usize; word.len()];
+    let mut k = 0;
+    for idx in 1..word.len() {
+        while k > 0 && word[idx] != word[k] {
+            k = failure[k - 1];
+        }
+        if word[idx] == word[k] {
+            k += 1;
+        }
+        failure[idx] = k;
+    }
+    let step = |mut state: usize, ch: &T| {
+        while state > 0 && word[state] != *ch {
+            state = failure[state - 1];
+        }
+        if word[state] == *ch {
+            state + 1
         } else {
-            if word.len() <= max {
-                queue.push_back(word.to_string());
+            0
+        }
+    };
+    let mut states = vec![0u128; word.len() + 1];
+    states[0] = 1;
+    for _ in 0..len {
+        let mut next = vec![0u128; word.len() + 1];
+        for (state, &count) in states.iter().enumerate() {
+            if count == 0 {
+                continue;
             }
-            None
-        };
+            for ch in alphabet {
+                let target = if state == word.len() {
+                    state
+                } else {
+                    step(state, ch)
+                };
+                next[target] = next[target].saturating_add(count);
+            }
+        }
+        states = next;
+    }
+    states[word.len()]
+}
+
+impl LengthIterator {
+    /// with a spill config the queue only ever holds the word itself, and
+    /// everything grown from it goes to the spilled frontier
+    fn new(word: &str, config: &LengthConfig, spill: Option<SpillConfig>) -> Self {
+        let (max, dedup, unit) = (config.max, config.dedup, config.unit);
+        let mut queue = VecDeque::new();
+        if unit.measure(word) <= max {
+            queue.push_back(word.to_string());
+        }
+        let spill = spill.map(|config| SpillFrontier::new(config, dedup));
+        let seen = (spill.is_none() && dedup).then(|| queue.iter().cloned().collect());
         Self {
             queue,
             seen,
-            chars: chars.chars().collect(),
-            min,
+            spill,
+            chars: config.chars.chars().collect(),
+            min: config.min,
             max,
-            do_append,
-            do_prepend,
-            do_insert,
+            unit,
+            do_append: config.append,
+            do_prepend: config.prepend,
+            do_insert: config.insert,
         }
     }
 
-    fn push_candidate(&mut self, candidate: String) {
-        if candidate.len() > self.max {
-            return;
+    fn push_candidate(&mut self, candidate: String) -> io::Result<()> {
+        if self.unit.measure(&candidate) > self.max {
+            return Ok(());
+        }
+        if let Some(spill) = self.spill.as_mut() {
+            return spill.push(candidate);
         }
         if let Some(seen) = self.seen.as_mut() {
             if !seen.insert(candidate.clone()) {
-                return;
+                return Ok(());
             }
         }
         self.queue.push_back(candidate);
+        Ok(())
+    }
+
+    fn pop_frontier(&mut self) -> io::Result<Option<String>> {
+        if let Some(current) = self.queue.pop_front() {
+            return Ok(Some(current));
+        }
+        match self.spill.as_mut() {
+            Some(spill) => spill.pop(),
+            None => Ok(None),
+        }
+    }
+
+    fn next_candidate(&mut self) -> io::Result<Option<String>> {
+        while let Some(current) = self.pop_frontier()? {
+            self.expand_current(&current)?;
+            let len = self.unit.measure(&current);
+            if len >= self.min && len <= self.max {
+                return Ok(Some(current));
+            }
+        }
+        Ok(None)
     }
 
-    fn expand_current(&mut self, current: &str) {
+    fn expand_current(&mut self, current: &str) -> io::Result<()> {
         for idx in 0..self.chars.len() {
             let ch = self.chars[idx];
             if self.do_append {
                 let mut cand = String::with_capacity(current.len() + 1);
                 cand.push_str(current);
                 cand.push(ch);
-                self.push_candidate(cand);
+                self.push_candidate(cand)?;
             }
             if self.do_prepend {
                 let mut cand = String::with_capacity(current.len() + 1);
                 cand.push(ch);
                 cand.push_str(current);
-                self.push_candidate(cand);
+                self.push_candidate(cand)?;
             }
             if self.do_insert {
-                for pos in 0..=current.len() {
+                for pos in self.unit.insert_positions(current) {
                     let mut cand = current.to_string();
                     cand.insert(pos, ch);
-                    self.push_candidate(cand);
+                    self.push_candidate(cand)?;
                 }
             }
         }
+        Ok(())
     }
 }
 
+/// ends after the first error, as the frontier is incomplete from then on
 impl Iterator for LengthIterator {
-    type Item = String;
+    type Item = io::Result<String>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(current) = self.queue.pop_front() {
-            self.expand_current(&current);
-            if current.len() >= self.min && current.len() <= self.max {
-                return Some(current);
+        match self.next_candidate() {
+            Ok(candidate) => candidate.map(Ok),
+            Err(err) => {
+                self.queue.clear();
+                self.spill = None;
+                Some(Err(err))
             }
         }
-        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(chars: &str, flags: (bool, bool, bool), dedup: bool) -> LengthConfig {
+        let (append, prepend, insert) = flags;
+        LengthConfig::new(chars)
+            .min(1)
+            .max(5)
+            .append(append)
+            .prepend(prepend)
+            .insert(insert)
+            .dedup(dedup)
+    }
+
+    fn generated(word: &str, chars: &str, flags: (bool, bool, bool), dedup: bool) -> u128 {
+        config(chars, flags, dedup).stream(word).count() as u128
+    }
+
+    fn estimated(word: &str, chars: &str, flags: (bool, bool, bool), dedup: bool) -> u128 {
+        config(chars, flags, dedup)
+            .keyspace(word)
+            .into_iter()
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    #[test]
+    fn keyspace_matches_generated_variants() {
+        let flag_sets = [
+            (true, false, false),
+            (false, true, false),
+            (true, true, false),
+            (false, false, true),
+            (true, true, true),
+        ];
+        for word in ["ab", "aa", "x1"] {
+            for chars in ["12", "a1", "ab"] {
+                for flags in flag_sets {
+                    for dedup in [true, false] {
+                        assert_eq!(
+                            estimated(word, chars, flags, dedup),
+                            generated(word, chars, flags, dedup),
+                            "{word} {chars} {flags:?} {dedup}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spilled_frontier_matches_in_memory_variants() {
+        let spill = SpillConfig {
+            threshold: 3,
+            dir: None,
+        };
+        for dedup in [true, false] {
+            let config = config("12a", (true, true, true), dedup).min(2);
+            for word in ["ab", "a\r"] {
+                let mut in_memory: Vec<String> = config.stream(word).collect();
+                let mut spilled: Vec<String> = config
+                    .stream_spilled(word, &spill)
+                    .collect::<Result<_>>()
+                    .unwrap();
+                in_memory.sort();
+                spilled.sort();
+                assert_eq!(in_memory, spilled);
+            }
+        }
+    }
+
+    #[test]
+    fn merges_more_runs_than_the_fan_in() {
+        // one run per candidate, thousands of them in the deepest level
+        let spill = SpillConfig {
+            threshold: 1,
+            dir: None,
+        };
+        let config = config("0123456789", (true, false, true), true).max(5);
+        let mut in_memory: Vec<String> = config.stream("ab").collect();
+        let mut spilled: Vec<String> = config
+            .stream_spilled("ab", &spill)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert!(in_memory.len() > 64 * 64);
+        in_memory.sort();
+        spilled.sort();
+        assert_eq!(in_memory, spilled);
+    }
+
+    #[test]
+    fn spill_errors_end_the_stream() {
+        let missing = SpillConfig {
+            threshold: 1,
+            dir: Some("/nonexistent/cemplox-spill".into()),
+        };
+        let config = config("12", (true, false, false), true);
+        let out: Vec<Result<String>> = config.stream_spilled("ab", &missing).collect();
+        assert!(matches!(out.last(), Some(Err(Error::Spill(_)))));
+        assert_eq!(out.iter().filter(|candidate| candidate.is_err()).count(), 1);
+    }
+
+    #[test]
+    fn measures_lengths_in_the_configured_unit() {
+        let config = LengthConfig::new("1")
+            .min(3)
+            .max(4)
+            .append(true)
+            .insert(true);
+        let generated: Vec<String> = config.clone().stream("\u{e9}t\u{e9}").collect();
+        assert_eq!(
+            generated,
+            vec![
+                "\u{e9}t\u{e9}",
+                "\u{e9}t\u{e9}1",
+                "1\u{e9}t\u{e9}",
+                "\u{e9}1t\u{e9}",
+                "\u{e9}t1\u{e9}"
+            ]
+        );
+        let estimated: u128 = config
+            .keyspace("\u{e9}t\u{e9}")
+            .into_iter()
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(estimated, 5);
+        let bytes = config.unit(LengthUnit::Bytes).max(6);
+        let generated: Vec<String> = bytes.stream("\u{e9}t\u{e9}").collect();
+        assert_eq!(
+            generated,
+            vec![
+                "\u{e9}t\u{e9}",
+                "\u{e9}t\u{e9}1",
+                "1\u{e9}t\u{e9}",
+                "\u{e9}1t\u{e9}",
+                "\u{e9}t1\u{e9}"
+            ]
+        );
+        let graphemes = LengthConfig::new("12")
+            .min(1)
+            .max(5)
+            .insert(true)
+            .unit(LengthUnit::Graphemes);
+        let word = "ne\u{301}";
+        let generated = graphemes.stream(word).count() as u128;
+        let estimated: u128 = graphemes
+            .keyspace(word)
+            .into_iter()
+            .map(|(_, count)| count)
+            .sum();
+        assert_eq!(generated, 111);
+        assert_eq!(estimated, generated);
     }
 }
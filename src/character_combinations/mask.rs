@@ -0,0 +1,224 @@
+use crate::error::{Error, Result};
+
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SPECIAL: &str = " !\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+const HEX_LOWER: &str = "0123456789abcdef";
+const HEX_UPPER: &str = "0123456789ABCDEF";
+
+/// a hashcat-style mask: one charset per position. the default mask has no
+/// positions and yields a single empty candidate
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Mask {
+    /// the mask text of each position, `?d` or a literal char
+    tokens: Vec<String>,
+    positions: Vec<Vec<char>>,
+}
+
+impl Mask {
+    /// parse `?u?l?l?d`-style masks. `?1`-`?4` refer to `custom`, which may
+    /// use the built-in charsets themselves (`?l?d`)
+    pub fn parse(text: &str, custom: &[Option<String>]) -> Result<Self> {
+        let mut custom_sets = Vec::with_capacity(custom.len());
+        for (idx, charset) in custom.iter().enumerate() {
+            let set = match charset {
+                Some(charset) => Some(
+                    parse_charset(charset, &[])
+                        .map_err(|err| Error::Mask(format!("custom charset {}: {err}", idx + 1)))?,
+                ),
+                None => None,
+            };
+            custom_sets.push(set);
+        }
+        let mut tokens = Vec::new();
+        let mut positions = Vec::new();
+        let mut chars = text.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '?' {
+                tokens.push(ch.to_string());
+                positions.push(vec![ch]);
+                continue;
+            }
+            let name = chars
+                .next()
+                .ok_or_else(|| Error::Mask(format!("'{text}' ends with a lone '?'")))?;
+            tokens.push(format!("?{name}"));
+            positions.push(placeholder(name, &custom_sets).map_err(Error::Mask)?);
+        }
+        if positions.is_empty() {
+            return Err(Error::Mask("mask is empty".to_string()));
+        }
+        if positions.iter().any(Vec::is_empty) {
+            return Err(Error::Mask(format!("'{text}' uses an empty charset")));
+        }
+        Ok(Self { tokens, positions })
+    }
+
+    /// the masks `--increment` walks: the first `min..=max` positions
+    pub fn prefixes(&self, min: usize, max: usize) -> Vec<Mask> {
+        (min.max(1)..=max.min(self.positions.len()))
+            .map(|len| Mask {
+                tokens: self.tokens[..len].to_vec(),
+                positions: self.positions[..len].to_vec(),
+            })
+            .collect()
+    }
+
+    pub fn text(&self) -> String {
+        self.tokens.concat()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn keyspace(&self) -> u128 {
+        self.positions
+            .iter()
+            .fold(1u128, |total, set| total.saturating_mul(set.len() as u128))
+    }
+
+    /// output bytes of the whole keyspace, newlines excluded
+    pub fn keyspace_bytes(&self) -> u128 {
+        let keyspace = self.keyspace();
+        self.positions
+            .iter()
+            .map(|set| {
+                let bytes: usize = set.iter().map(|ch| ch.len_utf8()).sum();
+                (keyspace / set.len() as u128).saturating_mul(bytes as u128)
+            })
+            .fold(0u128, u128::saturating_add)
+    }
+
+    /// candidates `start..end` of the keyspace. the last position varies fastest
+    pub fn candidates(&self, start: u128, end: u128) -> MaskIterator<'_> {
+        let mut state = vec![0; self.positions.len()];
+        let mut rest = start;
+        for (idx, set) in self.positions.iter().enumerate().rev() {
+            let radix = set.len() as u128;
+            state[idx] = (rest % radix) as usize;
+            rest /= radix;
+        }
+        MaskIterator {
+            mask: self,
+            state,
+            remaining: end.min(self.keyspace()).saturating_sub(start),
+        }
+    }
+}
+
+pub struct MaskIterator<'a> {
+    mask: &'a Mask,
+    state: Vec<usize>,
+    remaining: u128,
+}
+
+impl Iterator for MaskIterator<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let out: String = self
+            .state
+            .iter()
+            .zip(&self.mask.positions)
+            .map(|(&idx, set)| set[idx])
+            .collect();
+        for (idx, set) in self.mask.positions.iter().enumerate().rev() {
+            self.state[idx] += 1;
+            if self.state[idx] < set.len() {
+                break;
+            }
+            self.state[idx] = 0;
+        }
+        Some(out)
+    }
+}
+
+/// literal chars and placeholders, deduplicated in order of appearance
+fn parse_charset(text: &str, custom: &[Option<Vec<char>>]) -> Result<Vec<char>, String> {
+    let mut set = Vec::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        let added = if ch == '?' {
+            let name = chars
+                .next()
+                .ok_or_else(|| format!("'{text}' ends with a lone '?'"))?;
+            placeholder(name, custom)?
+        } else {
+            vec![ch]
+        };
+        for ch in added {
+            if !set.contains(&ch) {
+                set.push(ch);
+            }
+        }
+    }
+    Ok(set)
+}
+
+fn placeholder(name: char, custom: &[Option<Vec<char>>]) -> Result<Vec<char>, String> {
+    let builtin = match name {
+        'l' => LOWER.to_string(),
+        'u' => UPPER.to_string(),
+        'd' => DIGITS.to_string(),
+        's' => SPECIAL.to_string(),
+        'a' => format!("{LOWER}{UPPER}{DIGITS}{SPECIAL}"),
+        'h' => HEX_LOWER.to_string(),
+        'H' => HEX_UPPER.to_string(),
+        '?' => "?".to_string(),
+        'b' => return Err("?b is not supported: candidates are written as UTF-8 text".to_string()),
+        '1'..='4' => {
+            let idx = name as usize - '1' as usize;
+            return match custom.get(idx) {
+                Some(Some(set)) => Ok(set.clone()),
+                _ => Err(format!(
+                    "?{name} is used but custom charset {name} is not set"
+                )),
+            };
+        }
+        _ => return Err(format!("unknown placeholder ?{name}")),
+    };
+    Ok(builtin.chars().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_placeholders_and_custom_charsets() {
+        let custom = [Some("?dx".to_string()), None, None, None];
+        let mask = Mask::parse("a?1??", &custom).unwrap();
+        assert_eq!(mask.keyspace(), 11);
+        let all: Vec<String> = mask.candidates(0, u128::MAX).collect();
+        assert_eq!(all.first().map(String::as_str), Some("a0?"));
+        assert_eq!(all.last().map(String::as_str), Some("ax?"));
+        assert_eq!(mask.keyspace_bytes(), 33);
+        assert!(Mask::parse("?2", &custom).is_err());
+        assert!(Mask::parse("?d?", &custom).is_err());
+        assert!(Mask::parse("?b", &custom).is_err());
+    }
+
+    #[test]
+    fn ranges_cover_the_keyspace_once() {
+        let mask = Mask::parse("?d?h", &[]).unwrap();
+        let whole: Vec<String> = mask.candidates(0, mask.keyspace()).collect();
+        let split: Vec<String> = (0..mask.keyspace())
+            .step_by(7)
+            .flat_map(|start| mask.candidates(start, start + 7))
+            .collect();
+        assert_eq!(whole.len(), 160);
+        assert_eq!(whole, split);
+        let lens: Vec<usize> = mask.prefixes(1, 5).iter().map(Mask::len).collect();
+        assert_eq!(lens, vec![1, 2]);
+    }
+}
@@ -1,5 +1,13 @@
 mod length;
 mod count;
+mod hybrid;
+mod mask;
+mod spill;
+mod unit;
 
-pub use count::stream_count;
-pub use length::stream_length;
+pub use count::CountConfig;
+pub use hybrid::{hybrid_keyspace, stream_hybrid};
+pub use length::LengthConfig;
+pub use mask::{Mask, MaskIterator};
+pub use spill::SpillConfig;
+pub use unit::LengthUnit;
@@ -0,0 +1,185 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Split, Write};
+use std::path::PathBuf;
+use tempfile::{NamedTempFile, TempPath};
+
+#[derive(Clone, Debug)]
+pub struct SpillConfig {
+    pub threshold: usize,
+    pub dir: Option<PathBuf>,
+}
+
+/// most run files a merge reads at once, so a level never holds more than
+/// this many files open however many runs it spilled
+const MERGE_FAN_IN: usize = 64;
+
+/// bfs frontier that is consumed one level (one added char) at a time. the next
+/// level stays in memory until `threshold` candidates, then goes to sorted run
+/// files which are merge-deduplicated when the level starts, `MERGE_FAN_IN`
+/// runs per pass. candidates of different levels never collide, so dedup never
+/// has to look across levels. run files hold one candidate per `\n`, so a
+/// candidate ending in `\r` reads back unchanged
+pub struct SpillFrontier {
+    config: SpillConfig,
+    dedup: bool,
+    current: Level,
+    pending: Vec<String>,
+    /// closed run files, deleted once dropped
+    runs: Vec<TempPath>,
+}
+
+type Run = Split<BufReader<File>>;
+
+enum Level {
+    Memory(std::vec::IntoIter<String>),
+    /// runs read one after the other, each opened only when its turn comes
+    Disk {
+        runs: VecDeque<TempPath>,
+        reader: Option<(Run, TempPath)>,
+    },
+}
+
+impl SpillFrontier {
+    pub fn new(config: SpillConfig, dedup: bool) -> Self {
+        Self {
+            config,
+            dedup,
+            current: Level::Memory(Vec::new().into_iter()),
+            pending: Vec::new(),
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, candidate: String) -> io::Result<()> {
+        self.pending.push(candidate);
+        if self.pending.len() >= self.config.threshold.max(1) {
+            self.spill_pending()?;
+        }
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(next) = self.current.next()? {
+                return Ok(Some(next));
+            }
+            if self.pending.is_empty() && self.runs.is_empty() {
+                return Ok(None);
+            }
+            self.current = self.start_next_level()?;
+        }
+    }
+
+    fn start_next_level(&mut self) -> io::Result<Level> {
+        if self.runs.is_empty() {
+            let mut level = std::mem::take(&mut self.pending);
+            if self.dedup {
+                level.sort_unstable();
+                level.dedup();
+            }
+            return Ok(Level::Memory(level.into_iter()));
+        }
+        self.spill_pending()?;
+        let mut runs = std::mem::take(&mut self.runs);
+        if self.dedup {
+            while runs.len() > 1 {
+                let mut merged = Vec::with_capacity(runs.len().div_ceil(MERGE_FAN_IN));
+                let mut rest = runs.into_iter();
+                loop {
+                    let group: Vec<TempPath> = rest.by_ref().take(MERGE_FAN_IN).collect();
+                    if group.is_empty() {
+                        break;
+                    }
+                    merged.push(self.merge_runs(group)?);
+                }
+                runs = merged;
+            }
+        }
+        Ok(Level::Disk {
+            runs: runs.into(),
+            reader: None,
+        })
+    }
+
+    fn spill_pending(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        if self.dedup {
+            self.pending.sort_unstable();
+            self.pending.dedup();
+        }
+        let mut writer = BufWriter::new(self.tempfile()?);
+        for candidate in self.pending.drain(..) {
+            writer.write_all(candidate.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        let run = writer.into_inner().map_err(|err| err.into_error())?;
+        self.runs.push(run.into_temp_path());
+        Ok(())
+    }
+
+    /// merge sorted runs into one, dropping duplicates. the inputs are closed
+    /// and deleted once it is written
+    fn merge_runs(&self, runs: Vec<TempPath>) -> io::Result<TempPath> {
+        let mut readers = Vec::with_capacity(runs.len());
+        for run in &runs {
+            readers.push(BufReader::new(File::open(run)?).split(b'\n'));
+        }
+        let mut heap = BinaryHeap::new();
+        for (idx, reader) in readers.iter_mut().enumerate() {
+            if let Some(line) = reader.next() {
+                heap.push(Reverse((line?, idx)));
+            }
+        }
+        let mut writer = BufWriter::new(self.tempfile()?);
+        let mut last: Option<Vec<u8>> = None;
+        while let Some(Reverse((line, idx))) = heap.pop() {
+            if let Some(next) = readers[idx].next() {
+                heap.push(Reverse((next?, idx)));
+            }
+            if last.as_ref() == Some(&line) {
+                continue;
+            }
+            writer.write_all(&line)?;
+            writer.write_all(b"\n")?;
+            last = Some(line);
+        }
+        let merged = writer.into_inner().map_err(|err| err.into_error())?;
+        Ok(merged.into_temp_path())
+    }
+
+    fn tempfile(&self) -> io::Result<NamedTempFile> {
+        match &self.config.dir {
+            Some(dir) => NamedTempFile::new_in(dir),
+            None => NamedTempFile::new(),
+        }
+    }
+}
+
+impl Level {
+    fn next(&mut self) -> io::Result<Option<String>> {
+        match self {
+            Level::Memory(iter) => Ok(iter.next()),
+            Level::Disk { runs, reader } => loop {
+                if reader.is_none() {
+                    let Some(run) = runs.pop_front() else {
+                        return Ok(None);
+                    };
+                    *reader = Some((BufReader::new(File::open(&run)?).split(b'\n'), run));
+                }
+                let (lines, _) = reader.as_mut().expect("a run was just opened");
+                match lines.next() {
+                    Some(line) => {
+                        return String::from_utf8(line?)
+                            .map(Some)
+                            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+                    }
+                    None => *reader = None,
+                }
+            },
+        }
+    }
+}
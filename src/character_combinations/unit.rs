@@ -0,0 +1,86 @@
+use crate::error::{Error, Result};
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// how candidate lengths are measured. chars are inserted at char boundaries,
+/// or at grapheme boundaries when lengths count graphemes, so `é` written as
+/// `e` and a combining accent is never split
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LengthUnit {
+    Bytes,
+    #[default]
+    Chars,
+    Graphemes,
+}
+
+impl FromStr for LengthUnit {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "bytes" => Ok(LengthUnit::Bytes),
+            "chars" => Ok(LengthUnit::Chars),
+            "graphemes" => Ok(LengthUnit::Graphemes),
+            _ => Err(Error::UnknownName {
+                kind: "length unit",
+                name: name.to_string(),
+                expected: "bytes, chars or graphemes",
+            }),
+        }
+    }
+}
+
+impl LengthUnit {
+    pub fn measure(self, text: &str) -> usize {
+        match self {
+            LengthUnit::Bytes => text.len(),
+            LengthUnit::Chars => text.chars().count(),
+            LengthUnit::Graphemes => text.graphemes(true).count(),
+        }
+    }
+
+    /// byte offsets a char may be inserted at, both ends included
+    pub fn insert_positions(self, text: &str) -> Vec<usize> {
+        let mut positions: Vec<usize> = match self {
+            LengthUnit::Bytes | LengthUnit::Chars => {
+                text.char_indices().map(|(idx, _)| idx).collect()
+            }
+            LengthUnit::Graphemes => text.grapheme_indices(true).map(|(idx, _)| idx).collect(),
+        };
+        positions.push(text.len());
+        positions
+    }
+
+    /// the text split at its insert positions
+    pub(super) fn symbols(self, text: &str) -> Vec<&str> {
+        self.insert_positions(text)
+            .windows(2)
+            .map(|pair| &text[pair[0]..pair[1]])
+            .collect()
+    }
+
+    /// how much a single added char counts for, given the charset's average
+    /// byte width
+    pub(super) fn added_width(self, byte_width: usize) -> usize {
+        match self {
+            LengthUnit::Bytes => byte_width,
+            LengthUnit::Chars | LengthUnit::Graphemes => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_and_inserts_by_unit() {
+        let word = "ae\u{301}";
+        assert_eq!(LengthUnit::Bytes.measure(word), 4);
+        assert_eq!(LengthUnit::Chars.measure(word), 3);
+        assert_eq!(LengthUnit::Graphemes.measure(word), 2);
+        assert_eq!(LengthUnit::Chars.insert_positions(word), vec![0, 1, 2, 4]);
+        assert_eq!(LengthUnit::Graphemes.insert_positions(word), vec![0, 1, 4]);
+        assert!("runes".parse::<LengthUnit>().is_err());
+    }
+}
@@ -1,19 +1,57 @@
-use clap::{Args, Parser, Subcommand};
-use num_cpus;
+use crate::pipeline::{parse_size, ClassRequirement, Compression};
+use cemplox::case_combinations::CaseMode;
+use cemplox::character_combinations::LengthUnit;
+use cemplox::leet_combinations::LeetPreset;
+use cemplox::phrase_combinations::PhraseStyle;
+use cemplox::sanitize::MetadataPack;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 pub struct Cli {
     #[arg(short, long)]
-    pub file: String,
+    pub file: Vec<String>,
     #[arg(short, long)]
     pub out_file: Option<String>,
+    #[arg(long)]
+    pub compress: Option<Compression>,
+    #[arg(long, requires = "out_file", value_parser = parse_size)]
+    pub split_bytes: Option<u64>,
+    #[arg(long, requires = "out_file")]
+    pub split_lines: Option<u64>,
+    #[arg(long, requires = "out_file")]
+    pub split_by_length: bool,
     #[arg(short, long)]
     pub sanitize: bool,
+    #[arg(long)]
+    pub no_transliterate: bool,
+    #[arg(long)]
+    pub keep_unicode: bool,
+    #[arg(long, value_delimiter = ',', default_value = "en")]
+    pub metadata_packs: Vec<MetadataPack>,
+    #[arg(long)]
+    pub metadata_config: Option<String>,
+    #[arg(long)]
+    pub year_variants: bool,
+    #[arg(long)]
+    pub sanitize_min_len: Option<usize>,
+    #[arg(long)]
+    pub sanitize_max_len: Option<usize>,
+    #[arg(long)]
+    pub harvest_years: Option<String>,
+    #[arg(long)]
+    pub phrases: bool,
+    #[arg(long, value_delimiter = ',')]
+    pub phrase_styles: Vec<PhraseStyle>,
     #[arg(short, long)]
     pub case: bool,
     #[arg(short, long)]
     pub leet: bool,
+    #[arg(short, long)]
+    pub rules: Option<String>,
+    #[arg(long, value_delimiter = ',')]
+    pub pipeline: Vec<Stage>,
     #[arg(short = 'C', long, default_value = "1234567890!@#$%^&*()-=_+[]{} ")]
     pub chars: String,
     #[command(subcommand)]
@@ -24,16 +62,74 @@ pub struct Cli {
     pub log_level: String,
     #[arg(long)]
     pub max_rss_mb: Option<u64>,
+    #[arg(long, value_delimiter = ',', default_value = "full")]
+    pub case_mode: Vec<CaseMode>,
     #[arg(long)]
     pub case_max_changes: Option<usize>,
     #[arg(long)]
     pub leet_max_substitutions: Option<usize>,
+    #[arg(long, default_value = "basic")]
+    pub leet_preset: LeetPreset,
+    #[arg(long)]
+    pub leet_table: Option<String>,
+    #[arg(long)]
+    pub include_regex: Vec<String>,
+    #[arg(long)]
+    pub exclude_regex: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    pub require_classes: Vec<ClassRequirement>,
+    #[arg(long)]
+    pub min_len: Option<usize>,
+    #[arg(long)]
+    pub max_len: Option<usize>,
+    #[arg(long, default_value = "chars")]
+    pub length_unit: LengthUnit,
+    #[arg(long, conflicts_with_all = ["out_file", "checkpoint", "resume"])]
+    pub dry_run: bool,
+    #[arg(long)]
+    pub dedup: bool,
+    #[arg(long, requires = "dedup")]
+    pub dedup_fp_rate: Option<f64>,
+    #[arg(long, requires = "dedup_fp_rate", default_value_t = 100_000_000)]
+    pub dedup_capacity: u64,
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+    #[arg(long, requires_all = ["checkpoint", "out_file"])]
+    pub resume: bool,
+    #[arg(long, default_value_t = 30)]
+    pub checkpoint_interval: u64,
+    #[arg(long, conflicts_with = "dedup")]
+    pub ordered: bool,
+    #[arg(long, requires = "ordered")]
+    pub reorder_window: Option<usize>,
+    #[arg(long, requires = "ordered", value_parser = parse_size)]
+    pub reorder_bytes: Option<u64>,
+    #[arg(short, long)]
+    pub tempfile_mode: bool,
+    #[arg(long)]
+    pub tempfile_dir: Option<PathBuf>,
+    #[arg(long, default_value_t = 1_000_000)]
+    pub tempfile_threshold: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Stage {
+    Sanitize,
+    Phrase,
+    Case,
+    Leet,
+    Rules,
+    /// the subcommand: length, count, hybrid or combine
+    Chars,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Length(LengthArgs),
     Count(CountArgs),
+    Mask(MaskArgs),
+    Hybrid(HybridArgs),
+    Combine(CombineArgs),
 }
 
 #[derive(Args, Debug)]
@@ -61,3 +157,65 @@ pub struct CountArgs {
     #[arg(short, long, default_value_t = 0)]
     pub insert: usize,
 }
+
+#[derive(Args, Debug)]
+pub struct MaskArgs {
+    pub mask: String,
+    #[command(flatten)]
+    pub charsets: CustomCharsets,
+    #[arg(long)]
+    pub increment: bool,
+    #[arg(long, requires = "increment", default_value_t = 1)]
+    pub increment_min: usize,
+    #[arg(long, requires = "increment")]
+    pub increment_max: Option<usize>,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("masks").required(true).multiple(true)))]
+pub struct HybridArgs {
+    #[arg(short, long, group = "masks")]
+    pub append: Option<String>,
+    #[arg(short, long, group = "masks")]
+    pub prepend: Option<String>,
+    #[command(flatten)]
+    pub charsets: CustomCharsets,
+}
+
+#[derive(Args, Debug)]
+pub struct CombineArgs {
+    pub right_file: String,
+    #[arg(long)]
+    pub separator: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    pub right_pipeline: Vec<Stage>,
+    #[arg(long, default_value_t = 0)]
+    pub right_append: usize,
+    #[arg(long, default_value_t = 0)]
+    pub right_prepend: usize,
+    #[arg(long, default_value_t = 0)]
+    pub right_insert: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct CustomCharsets {
+    #[arg(short = '1', long)]
+    pub custom_charset1: Option<String>,
+    #[arg(short = '2', long)]
+    pub custom_charset2: Option<String>,
+    #[arg(short = '3', long)]
+    pub custom_charset3: Option<String>,
+    #[arg(short = '4', long)]
+    pub custom_charset4: Option<String>,
+}
+
+impl CustomCharsets {
+    pub fn to_vec(&self) -> Vec<Option<String>> {
+        vec![
+            self.custom_charset1.clone(),
+            self.custom_charset2.clone(),
+            self.custom_charset3.clone(),
+            self.custom_charset4.clone(),
+        ]
+    }
+}
@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+/// everything the library can fail with
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid leet table: {0}")]
+    LeetTable(String),
+    #[error("invalid rule: {0}")]
+    Rule(String),
+    #[error("no valid rules in {}", .0.display())]
+    NoRules(PathBuf),
+    #[error("invalid mask: {0}")]
+    Mask(String),
+    #[error("invalid metadata config: {0}")]
+    Metadata(String),
+    #[error("failed to spill the length frontier to temp files")]
+    Spill(#[source] std::io::Error),
+    #[error("unknown {kind} '{name}' (expected {expected})")]
+    UnknownName {
+        kind: &'static str,
+        name: String,
+        expected: &'static str,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
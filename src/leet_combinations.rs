@@ -1,103 +1,393 @@
-/// gen every combination of leet (a-->@) transformations in the word
-pub fn stream_leet(word: &str, max_substitutions: Option<usize>) -> impl Iterator<Item = String> {
-    LeetPermutations::new(word, max_substitutions)
-}
-struct LeetPermutations {
-    options: Vec<Vec<char>>,
-    indices: Vec<usize>,
-    finished: bool,
+use crate::error::{Error, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// gen every combination of leet (a-->@, ph-->f) transformations in the word
+pub fn stream_leet(
+    word: &str,
+    table: &LeetTable,
     max_substitutions: Option<usize>,
+) -> impl Iterator<Item = String> {
+    LeetPermutations::new(word, table, max_substitutions)
 }
 
-impl LeetPermutations {
-    fn new(word: &str, max_substitutions: Option<usize>) -> Self {
-        let mut options = Vec::new();
-        for ch in word.chars() {
-            let mut choices = Vec::new();
-            choices.push(ch);
-            for &sub in leet_variants_for(ch) {
-                if !choices.contains(&sub) {
-                    choices.push(sub);
+/// count the variants `stream_leet` would produce without generating them.
+/// substitutions can change the byte length, so counts are grouped by number
+/// of substitutions and length with one representative variant each. when
+/// overlapping sequence entries spell the same variant twice (`o=0` and
+/// `oo=00`) it is counted twice, so the count is an upper bound
+pub fn leet_keyspace(
+    word: &str,
+    table: &LeetTable,
+    max_substitutions: Option<usize>,
+) -> Vec<(String, u128)> {
+    let segments = segments_for(word, table);
+    let mut states: Vec<BTreeMap<(usize, usize), (u128, String)>> =
+        vec![BTreeMap::new(); segments.len() + 1];
+    states[0].insert((0, 0), (1, String::new()));
+    for (pos, choices) in segments.iter().enumerate() {
+        let current = std::mem::take(&mut states[pos]);
+        for (&(subs, bytes), (count, repr)) in &current {
+            for (idx, choice) in choices.iter().enumerate() {
+                let subs = subs + (idx > 0) as usize;
+                if max_substitutions.is_some_and(|limit| subs > limit) {
+                    continue;
                 }
+                let entry = states[pos + choice.span]
+                    .entry((subs, bytes + choice.text.len()))
+                    .or_insert_with(|| (0, format!("{repr}{}", choice.text)));
+                entry.0 = entry.0.saturating_add(*count);
             }
-            options.push(choices);
         }
-        let indices = vec![0; options.len()];
-        Self { options, indices, finished: false, max_substitutions }
     }
+    states
+        .pop()
+        .unwrap_or_default()
+        .into_values()
+        .map(|(count, repr)| (repr, count))
+        .collect()
+}
 
-    fn advance(&mut self) {
-        if self.indices.is_empty() {
-            self.finished = true;
-            return;
-        }
+/// one way to spell the word from a given char onwards: `span` chars replaced by `text`
+struct Segment {
+    span: usize,
+    text: String,
+}
 
-        for pos in (0..self.indices.len()).rev() {
-            self.indices[pos] += 1;
-            if self.indices[pos] < self.options[pos].len() {
-                for reset in pos + 1..self.indices.len() {
-                    self.indices[reset] = 0;
+/// per char position, the original char followed by every distinct substitution
+/// of a table entry that starts there
+fn segments_for(word: &str, table: &LeetTable) -> Vec<Vec<Segment>> {
+    let chars: Vec<char> = word.chars().collect();
+    let lowered: Vec<char> = chars.iter().map(|&ch| lowercase(ch)).collect();
+    (0..chars.len())
+        .map(|pos| {
+            let mut choices = vec![Segment {
+                span: 1,
+                text: chars[pos].to_string(),
+            }];
+            for span in 1..=table.longest.min(chars.len() - pos) {
+                let key: String = lowered[pos..pos + span].iter().collect();
+                let original: String = chars[pos..pos + span].iter().collect();
+                for sub in table.variants_for(&key) {
+                    let duplicate = (span == 1 && *sub == choices[0].text)
+                        || choices[1..]
+                            .iter()
+                            .any(|c| c.span == span && c.text == *sub);
+                    if !duplicate && *sub != original {
+                        choices.push(Segment {
+                            span,
+                            text: sub.clone(),
+                        });
+                    }
                 }
-                return;
             }
-            self.indices[pos] = 0;
+            choices
+        })
+        .collect()
+}
+
+/// built-in substitution tables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeetPreset {
+    None,
+    Basic,
+    Full,
+    Cyrillic,
+    Greek,
+}
+
+impl FromStr for LeetPreset {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "none" => Ok(LeetPreset::None),
+            "basic" => Ok(LeetPreset::Basic),
+            "full" => Ok(LeetPreset::Full),
+            "cyrillic" => Ok(LeetPreset::Cyrillic),
+            "greek" => Ok(LeetPreset::Greek),
+            _ => Err(Error::UnknownName {
+                kind: "leet preset",
+                name: name.to_string(),
+                expected: "none, basic, full, cyrillic or greek",
+            }),
         }
-        self.finished = true;
     }
 }
 
-impl Iterator for LeetPermutations {
-    type Item = String;
+impl LeetPreset {
+    fn entries(self) -> &'static str {
+        match self {
+            LeetPreset::None => "",
+            LeetPreset::Basic => {
+                "a=4,@\nb=8\ne=3\ng=6,9\nh=#\ni=1,!\nl=1\no=0\nq=9\ns=5,$\nt=7,+\nz=2"
+            }
+            LeetPreset::Full => {
+                "a=4,@,^\nb=8,6\nc=(,<,{,[\nd=)\ne=3,&\nf=#\ng=6,9,&\nh=#\ni=1,!,|\nj=]\n\
+                 k=<\nl=1,|,7\no=0\np=9\nq=9\nr=2\ns=5,$,z\nt=7,+\nu=v\nx=%\ny=j\nz=2,s\n\
+                 ck=x\nph=f\nate=8\nfor=4\nm=|\\/|\nn=|\\|\nv=\\/\nw=\\/\\/,vv\nx=><"
+            }
+            LeetPreset::Cyrillic => {
+                "a=\u{430}\nb=\u{44c}\nc=\u{441}\ne=\u{435}\nh=\u{4bb}\ni=\u{456}\nj=\u{458}\n\
+                 k=\u{43a}\nm=\u{43c}\no=\u{43e}\np=\u{440}\ns=\u{455}\nx=\u{445}\ny=\u{443}"
+            }
+            LeetPreset::Greek => {
+                "a=\u{3b1}\nb=\u{3b2}\ne=\u{3b5}\ni=\u{3b9}\nk=\u{3ba}\nn=\u{3b7}\no=\u{3bf}\n\
+                 p=\u{3c1}\nt=\u{3c4}\nu=\u{3c5}\nv=\u{3bd}\nx=\u{3c7}\ny=\u{3b3}"
+            }
+        }
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        while !self.finished {
-            let mut out = String::with_capacity(self.options.len());
-            let mut subs = 0usize;
-            for (idx, choices) in self.indices.iter().zip(&self.options) {
-                let choice_idx = *idx;
-                if choice_idx > 0 {
-                    subs += 1;
-                }
-                out.push(choices[choice_idx]);
+/// sequence to substitutions map. keys are lowercase and match either case
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LeetTable {
+    map: HashMap<String, Vec<String>>,
+    /// longest key, in chars
+    longest: usize,
+    /// whether two ways through a word can spell the same variant, which
+    /// only sequence entries make possible
+    repeats: bool,
+}
+
+impl LeetTable {
+    pub fn preset(preset: LeetPreset) -> Self {
+        Self::parse(preset.entries()).expect("built-in leet presets are valid")
+    }
+
+    /// read a table file, one `a=4,@` or `ph=f` entry per line. `#` starts a comment line
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text).map_err(|err| match err {
+            Error::LeetTable(message) => {
+                Error::LeetTable(format!("{}: {message}", path.display()))
+            }
+            other => other,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut table = LeetTable::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, values) = line
+                .split_once('=')
+                .ok_or_else(|| {
+                    Error::LeetTable(format!("line {}: expected `key=sub,sub`", idx + 1))
+                })?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(Error::LeetTable(format!("line {}: empty key", idx + 1)));
+            }
+            let subs = values
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect();
+            table.add(key, subs);
+        }
+        Ok(table)
+    }
+
+    /// entries of `other` are added after this table's own substitutions
+    pub fn extend(&mut self, other: LeetTable) {
+        for (key, subs) in other.map {
+            self.add(&key, subs);
+        }
+    }
+
+    /// true when no substitution has fewer chars than the sequence it replaces
+    pub fn never_shortens(&self) -> bool {
+        self.map.iter().all(|(key, subs)| {
+            subs.iter()
+                .all(|sub| sub.chars().count() >= key.chars().count())
+        })
+    }
+
+    fn add(&mut self, key: &str, subs: Vec<String>) {
+        let key: String = key.chars().map(lowercase).collect();
+        self.longest = self.longest.max(key.chars().count());
+        let entry = self.map.entry(key).or_default();
+        for sub in subs {
+            if !entry.contains(&sub) {
+                entry.push(sub);
             }
+        }
+        self.repeats = self.sequences_overlap();
+    }
+
+    /// sequence keys that share chars (`ab` and `bc`, or `o` inside `oo`),
+    /// or a sequence substitution its chars spell one by one (`oo=00` with `o=0`)
+    fn sequences_overlap(&self) -> bool {
+        let sequences: Vec<(&String, &Vec<String>)> = self
+            .map
+            .iter()
+            .filter(|(key, _)| key.chars().count() > 1)
+            .collect();
+        let shares_chars = |a: &str, b: &str| {
+            b.contains(a)
+                || a.char_indices()
+                    .skip(1)
+                    .any(|(idx, _)| b.starts_with(&a[idx..]))
+        };
+        sequences.iter().any(|(a, _)| {
+            sequences
+                .iter()
+                .any(|(b, _)| a != b && shares_chars(a, b))
+        }) || sequences.iter().any(|(key, subs)| {
+            let chars: Vec<char> = key.chars().collect();
+            subs.iter().any(|sub| self.spells(&chars, sub))
+        })
+    }
+
+    /// whether substituting `chars` one at a time can give `text`
+    fn spells(&self, chars: &[char], text: &str) -> bool {
+        let Some((&ch, rest)) = chars.split_first() else {
+            return text.is_empty();
+        };
+        let lower = ch.to_string();
+        let upper: String = ch.to_uppercase().collect();
+        let spelled = [&lower, &upper]
+            .into_iter()
+            .chain(self.variants_for(&lower))
+            .any(|choice| {
+                text.strip_prefix(choice.as_str())
+                    .is_some_and(|text| self.spells(rest, text))
+            });
+        spelled
+    }
+
+    fn variants_for(&self, key: &str) -> &[String] {
+        self.map.get(key).map_or(&[], Vec::as_slice)
+    }
+}
+
+fn lowercase(ch: char) -> char {
+    let mut lower = ch.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(single), None) => single,
+        _ => ch,
+    }
+}
+
+/// depth-first walk over segment choices; the deepest choice varies fastest
+struct LeetPermutations {
+    segments: Vec<Vec<Segment>>,
+    stack: Vec<Frame>,
+    out: String,
+    subs: usize,
+    started: bool,
+    /// only needed when overlapping sequence entries can spell the same
+    /// variant twice
+    seen: Option<HashSet<String>>,
+    max_substitutions: Option<usize>,
+}
+
+struct Frame {
+    pos: usize,
+    choice: usize,
+    out_len: usize,
+    subs: usize,
+}
+
+impl LeetPermutations {
+    fn new(word: &str, table: &LeetTable, max_substitutions: Option<usize>) -> Self {
+        let segments = segments_for(word, table);
+        let seen = (table.repeats
+            && segments.iter().flatten().any(|segment| segment.span > 1))
+        .then(HashSet::new);
+        Self {
+            segments,
+            stack: Vec::new(),
+            out: String::with_capacity(word.len()),
+            subs: 0,
+            started: false,
+            seen,
+            max_substitutions,
+        }
+    }
+
+    /// keep the original chars from `pos` to the end of the word
+    fn fill(&mut self, mut pos: usize) {
+        while pos < self.segments.len() {
+            self.stack.push(Frame {
+                pos,
+                choice: 0,
+                out_len: self.out.len(),
+                subs: self.subs,
+            });
+            self.out.push_str(&self.segments[pos][0].text);
+            pos += 1;
+        }
+    }
+
+    fn advance(&mut self) -> bool {
+        while let Some(frame) = self.stack.pop() {
+            self.out.truncate(frame.out_len);
+            self.subs = frame.subs;
+            let choice = frame.choice + 1;
             let allowed = self
                 .max_substitutions
-                .map_or(true, |limit| subs <= limit);
-            self.advance();
-            if allowed {
-                return Some(out);
+                .is_none_or(|limit| frame.subs < limit);
+            if !allowed || choice >= self.segments[frame.pos].len() {
+                continue;
             }
+            let segment = &self.segments[frame.pos][choice];
+            let next = frame.pos + segment.span;
+            self.out.push_str(&segment.text);
+            self.subs += 1;
+            self.stack.push(Frame { choice, ..frame });
+            self.fill(next);
+            return true;
         }
-        None
+        false
     }
 }
 
-fn leet_variants_for(ch: char) -> &'static [char] {
-    match ch.to_ascii_lowercase() {
-        'a' => &['4', '@'],
-        'b' => &['8'],
-        'e' => &['3'],
-        'g' => &['6', '9'],
-        'h' => &['#'],
-        'i' => &['1', '!'],
-        'l' => &['1'],
-        'o' => &['0'],
-        'q' => &['9'],
-        's' => &['5', '$'],
-        't' => &['7', '+'],
-        'z' => &['2'],
-        _ => &[],
+impl Iterator for LeetPermutations {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.started {
+                if !self.advance() {
+                    return None;
+                }
+            } else {
+                self.started = true;
+                self.fill(0);
+            }
+            if let Some(seen) = self.seen.as_mut() {
+                if !seen.insert(self.out.clone()) {
+                    continue;
+                }
+            }
+            return Some(self.out.clone());
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashSet;
+
+    fn basic() -> LeetTable {
+        LeetTable::preset(LeetPreset::Basic)
+    }
 
     #[test]
     fn includes_expected_leet_variants() {
-        let variants: Vec<String> = stream_leet("leet", None).collect();
+        let variants: Vec<String> = stream_leet("leet", &basic(), None).collect();
         let unique: HashSet<&String> = variants.iter().collect();
         assert_eq!(variants.len(), unique.len());
         assert!(unique.iter().any(|v| v.as_str() == "leet"));
@@ -107,13 +397,78 @@ mod tests {
 
     #[test]
     fn handles_empty_string() {
-        let variants: Vec<String> = stream_leet("", None).collect();
+        let variants: Vec<String> = stream_leet("", &basic(), None).collect();
         assert_eq!(variants, vec!["".to_string()]);
     }
 
+    #[test]
+    fn counts_match_generated_variants() {
+        for (word, limit) in [("leet", None), ("password", Some(2)), ("xyz", None)] {
+            let generated = stream_leet(word, &basic(), limit).count() as u128;
+            let estimated: u128 = leet_keyspace(word, &basic(), limit)
+                .into_iter()
+                .map(|(_, count)| count)
+                .sum();
+            assert_eq!(estimated, generated, "{word}");
+        }
+    }
+
+    #[test]
+    fn table_files_extend_presets() {
+        let mut table = basic();
+        table.extend(LeetTable::parse("# custom\nl = |\nh=").unwrap());
+        let variants: Vec<String> = stream_leet("hl", &table, None).collect();
+        assert!(variants.contains(&"#|".to_string()));
+        assert!(variants.contains(&"h1".to_string()));
+        assert!(LeetTable::parse("=4").is_err());
+        assert!(LeetTable::parse("a4").is_err());
+    }
+
+    #[test]
+    fn cyrillic_keyspace_groups_by_byte_length() {
+        let table = LeetTable::preset(LeetPreset::Cyrillic);
+        let by_len = leet_keyspace("ox", &table, None);
+        let lens: Vec<(usize, u128)> = by_len.iter().map(|(w, c)| (w.len(), *c)).collect();
+        assert_eq!(lens, vec![(2, 1), (3, 2), (4, 1)]);
+    }
+
+    #[test]
+    fn substitutes_sequences_without_duplicates() {
+        let table = LeetTable::parse("ph=f\nate=8\na=4\no=0\noo=00").unwrap();
+        let variants: Vec<String> = stream_leet("phate", &table, None).collect();
+        for expected in ["phate", "fate", "ph8", "f8", "ph4te", "f4te"] {
+            assert!(variants.contains(&expected.to_string()), "{expected}");
+        }
+        assert_eq!(variants.len(), 6);
+        let limited: Vec<String> = stream_leet("phate", &table, Some(1)).collect();
+        assert!(!limited.contains(&"f8".to_string()));
+        let variants: Vec<String> = stream_leet("Boot", &table, None).collect();
+        assert_eq!(variants, vec!["Boot", "Bo0t", "B0ot", "B00t"]);
+    }
+
+    #[test]
+    fn knows_which_tables_can_shorten_words() {
+        assert!(basic().never_shortens());
+        assert!(LeetTable::preset(LeetPreset::Cyrillic).never_shortens());
+        assert!(!LeetTable::preset(LeetPreset::Full).never_shortens());
+        assert!(!LeetTable::parse("ate=8").unwrap().never_shortens());
+        assert!(LeetTable::parse("m=|\\/|\noo=00").unwrap().never_shortens());
+    }
+
+    #[test]
+    fn tracks_repeats_only_for_overlapping_sequences() {
+        assert!(!LeetTable::preset(LeetPreset::Full).repeats);
+        assert!(!LeetTable::parse("ph=f\nate=8\na=4").unwrap().repeats);
+        assert!(LeetTable::parse("o=0\noo=00").unwrap().repeats);
+        assert!(LeetTable::parse("ab=1\nbc=2").unwrap().repeats);
+        let table = LeetTable::parse("ab=1\nbc=2\na=1\nc=2").unwrap();
+        let variants: Vec<String> = stream_leet("abc", &table, None).collect();
+        assert_eq!(variants.iter().filter(|v| *v == "12").count(), 1);
+    }
+
     #[test]
     fn respects_substitution_limit() {
-        let variants: Vec<String> = stream_leet("leet", Some(1)).collect();
+        let variants: Vec<String> = stream_leet("leet", &basic(), Some(1)).collect();
         assert!(variants.contains(&"leet".to_string()));
         assert!(!variants.contains(&"l337".to_string()));
     }
@@ -0,0 +1,16 @@
+//! wordlist candidate generation: sanitizing, phrase, case and leet
+//! permutations, hashcat rules, masks and length/count expansion. every generator is a
+//! plain iterator, and [`Candidates`] chains them over any stream of words
+
+pub mod case_combinations;
+pub mod character_combinations;
+pub mod leet_combinations;
+pub mod phrase_combinations;
+pub mod rules;
+pub mod sanitize;
+
+mod candidates;
+mod error;
+
+pub use candidates::Candidates;
+pub use error::{Error, Result};
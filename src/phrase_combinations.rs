@@ -0,0 +1,155 @@
+use crate::error::{Error, Result};
+use std::str::FromStr;
+
+/// ways to rebuild a spaced phrase like `star wars` from its words
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PhraseStyle {
+    /// `starwars`
+    Joined,
+    /// `star_wars`
+    Underscore,
+    /// `star-wars`
+    Dash,
+    /// `star.wars`
+    Dot,
+    /// `starWars`
+    Camel,
+    /// `StarWars`
+    Pascal,
+    /// `sw`
+    Initials,
+    /// `star`
+    First,
+    /// `wars`
+    Last,
+}
+
+impl PhraseStyle {
+    pub const ALL: [PhraseStyle; 9] = [
+        PhraseStyle::Joined,
+        PhraseStyle::Underscore,
+        PhraseStyle::Dash,
+        PhraseStyle::Dot,
+        PhraseStyle::Camel,
+        PhraseStyle::Pascal,
+        PhraseStyle::Initials,
+        PhraseStyle::First,
+        PhraseStyle::Last,
+    ];
+
+    fn apply(self, words: &[&str]) -> String {
+        match self {
+            PhraseStyle::Joined => words.concat(),
+            PhraseStyle::Underscore => words.join("_"),
+            PhraseStyle::Dash => words.join("-"),
+            PhraseStyle::Dot => words.join("."),
+            PhraseStyle::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(idx, word)| {
+                    if idx == 0 {
+                        with_first(word, char::to_lowercase)
+                    } else {
+                        with_first(word, char::to_uppercase)
+                    }
+                })
+                .collect(),
+            PhraseStyle::Pascal => words
+                .iter()
+                .map(|word| with_first(word, char::to_uppercase))
+                .collect(),
+            PhraseStyle::Initials => words
+                .iter()
+                .filter_map(|word| word.chars().next())
+                .collect(),
+            PhraseStyle::First => words.first().copied().unwrap_or_default().to_string(),
+            PhraseStyle::Last => words.last().copied().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+impl FromStr for PhraseStyle {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "joined" => Ok(PhraseStyle::Joined),
+            "underscore" => Ok(PhraseStyle::Underscore),
+            "dash" => Ok(PhraseStyle::Dash),
+            "dot" => Ok(PhraseStyle::Dot),
+            "camel" => Ok(PhraseStyle::Camel),
+            "pascal" => Ok(PhraseStyle::Pascal),
+            "initials" => Ok(PhraseStyle::Initials),
+            "first" => Ok(PhraseStyle::First),
+            "last" => Ok(PhraseStyle::Last),
+            _ => Err(Error::UnknownName {
+                kind: "phrase style",
+                name: name.to_string(),
+                expected: "joined, underscore, dash, dot, camel, pascal, initials, first or last",
+            }),
+        }
+    }
+}
+
+/// the phrase followed by the distinct variants of each style. words are
+/// split on whitespace, so single words and the squashed form `sanitize`
+/// also emits pass through unchanged
+pub fn stream_phrases(phrase: &str, styles: &[PhraseStyle]) -> impl Iterator<Item = String> {
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    let mut out = vec![phrase.to_string()];
+    if words.len() > 1 {
+        for style in styles {
+            let variant = style.apply(&words);
+            if !out.contains(&variant) {
+                out.push(variant);
+            }
+        }
+    }
+    out.into_iter()
+}
+
+fn with_first<I: Iterator<Item = char>>(word: &str, map: impl Fn(char) -> I) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => map(first).chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebuilds_phrases_from_their_words() {
+        let variants: Vec<String> = stream_phrases("star wars", &PhraseStyle::ALL).collect();
+        assert_eq!(
+            variants,
+            vec![
+                "star wars",
+                "starwars",
+                "star_wars",
+                "star-wars",
+                "star.wars",
+                "starWars",
+                "StarWars",
+                "sw",
+                "star",
+                "wars"
+            ]
+        );
+        let variants: Vec<String> = stream_phrases(
+            "Über den Wolken",
+            &[PhraseStyle::Camel, PhraseStyle::Initials],
+        )
+        .collect();
+        assert_eq!(variants, vec!["Über den Wolken", "überDenWolken", "ÜdW"]);
+    }
+
+    #[test]
+    fn single_words_pass_through() {
+        let variants: Vec<String> = stream_phrases("starwars", &PhraseStyle::ALL).collect();
+        assert_eq!(variants, vec!["starwars"]);
+        assert!("kebab".parse::<PhraseStyle>().is_err());
+    }
+}
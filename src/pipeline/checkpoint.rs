@@ -0,0 +1,236 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+
+const MAGIC: &str = "cemplox-checkpoint 1";
+
+/// which input chunks are fully written, and how long the output was when the
+/// checkpoint was taken. chunks are written in order, so that length ends right
+/// after the last done chunk and anything past it is dropped on resume
+pub struct Checkpoint {
+    path: PathBuf,
+    input: String,
+    input_len: u64,
+    chunk_size: usize,
+    output_bytes: u64,
+    chunks: usize,
+    /// runs of done chunks, keyed by their first chunk. neighbouring runs are
+    /// merged, so there are only as many as there are gaps
+    done: BTreeMap<usize, usize>,
+}
+
+impl Checkpoint {
+    pub fn new(
+        path: PathBuf,
+        input: &str,
+        input_len: u64,
+        chunk_size: usize,
+        chunks: usize,
+    ) -> Self {
+        Self {
+            path,
+            input: input.to_string(),
+            input_len,
+            chunk_size,
+            output_bytes: 0,
+            chunks,
+            done: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read checkpoint {}", path.display()))?;
+        let mut lines = text.lines();
+        if lines.next() != Some(MAGIC) {
+            bail!("{} is not a cemplox checkpoint", path.display());
+        }
+        let mut checkpoint = Self::new(path, "", 0, 0, 0);
+        let mut done = Vec::new();
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "input" => {
+                    let (len, input) = value.split_once(' ').unwrap_or((value, ""));
+                    checkpoint.input_len = len.parse()?;
+                    checkpoint.input = input.to_string();
+                }
+                "chunk_size" => checkpoint.chunk_size = value.parse()?,
+                "chunks" => checkpoint.chunks = value.parse()?,
+                "output_bytes" => checkpoint.output_bytes = value.parse()?,
+                "done" => done.extend(parse_ranges(value)?),
+                _ => bail!("unknown checkpoint entry '{key}'"),
+            }
+        }
+        for range in done {
+            if range.end > checkpoint.chunks {
+                bail!("checkpoint marks chunk {} done but only has {} chunks", range.end - 1, checkpoint.chunks);
+            }
+            checkpoint.insert(range);
+        }
+        Ok(checkpoint)
+    }
+
+    /// refuse to resume against a different input or chunking
+    pub fn validate(&self, input_len: u64, chunk_size: usize, chunks: usize) -> Result<()> {
+        if self.input_len != input_len || self.chunk_size != chunk_size || self.chunks != chunks {
+            bail!(
+                "checkpoint {} was taken for a different input ({}, {} bytes)",
+                self.path.display(),
+                self.input,
+                self.input_len
+            );
+        }
+        Ok(())
+    }
+
+    /// the chunks not done yet, in order
+    pub fn pending(&self) -> Vec<Range<usize>> {
+        let mut pending = Vec::new();
+        let mut next = 0;
+        for (&start, &end) in &self.done {
+            if start > next {
+                pending.push(next..start);
+            }
+            next = end;
+        }
+        if next < self.chunks {
+            pending.push(next..self.chunks);
+        }
+        pending
+    }
+
+    pub fn mark_done(&mut self, chunk: usize) {
+        if chunk < self.chunks {
+            self.insert(chunk..chunk + 1);
+        }
+    }
+
+    /// add `range` to the done runs, merging it with the runs it touches
+    fn insert(&mut self, mut range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        if let Some((&start, &end)) = self.done.range(..=range.start).next_back() {
+            if end >= range.start {
+                range = start..range.end.max(end);
+            }
+        }
+        let touched: Vec<usize> = self.done.range(range.start..=range.end).map(|(&start, _)| start).collect();
+        for start in touched {
+            let end = self.done.remove(&start).expect("run was just found");
+            range.end = range.end.max(end);
+        }
+        self.done.insert(range.start, range.end);
+    }
+
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.chunks - self.done.iter().map(|(start, end)| end - start).sum::<usize>()
+    }
+
+    /// record state after the output was flushed to `output_bytes`. written to a
+    /// temp file and renamed so a crash never leaves a torn checkpoint
+    pub fn save(&mut self, output_bytes: u64) -> Result<()> {
+        self.output_bytes = output_bytes;
+        let text = format!(
+            "{MAGIC}\ninput {} {}\nchunk_size {}\nchunks {}\noutput_bytes {}\ndone {}\n",
+            self.input_len,
+            self.input,
+            self.chunk_size,
+            self.chunks,
+            self.output_bytes,
+            format_ranges(self.done.iter().map(|(&start, &end)| start..end))
+        );
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, text)
+            .with_context(|| format!("failed to write checkpoint {}", self.path.display()))?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to write checkpoint {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// `0-14,16,18-20`, with inclusive ends
+fn format_ranges(ranges: impl Iterator<Item = Range<usize>>) -> String {
+    let parts: Vec<String> = ranges
+        .map(|range| match range.len() {
+            1 => range.start.to_string(),
+            _ => format!("{}-{}", range.start, range.end - 1),
+        })
+        .collect();
+    parts.join(",")
+}
+
+fn parse_ranges(value: &str) -> Result<Vec<Range<usize>>> {
+    let mut out = Vec::new();
+    for part in value.split(',').filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (start.parse::<usize>()?, end.parse::<usize>()?),
+            None => {
+                let idx = part.parse::<usize>()?;
+                (idx, idx)
+            }
+        };
+        if end < start {
+            bail!("checkpoint range '{part}' ends before it starts");
+        }
+        out.push(start..end.saturating_add(1));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_done_chunks_as_merged_runs() {
+        let mut checkpoint = Checkpoint::new(PathBuf::new(), "words.txt", 100, 10, 10);
+        for chunk in [3, 1, 2, 7, 9, 8, 12] {
+            checkpoint.mark_done(chunk);
+        }
+        assert_eq!(checkpoint.done.len(), 2);
+        assert_eq!(checkpoint.pending(), [0..1, 4..7]);
+        assert_eq!(checkpoint.remaining(), 4);
+    }
+
+    #[test]
+    fn formats_and_parses_ranges() {
+        let ranges = vec![0..15, 16..17, 18..21];
+        assert_eq!(format_ranges(ranges.clone().into_iter()), "0-14,16,18-20");
+        assert_eq!(parse_ranges("0-14,16,18-20").unwrap(), ranges);
+        assert!(parse_ranges("").unwrap().is_empty());
+        assert!(parse_ranges("5-3").is_err());
+        assert!(parse_ranges("1,x").is_err());
+    }
+
+    #[test]
+    fn saves_and_loads_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.ckpt");
+        let mut checkpoint = Checkpoint::new(path.clone(), "words.txt", 100, 10, 10);
+        for chunk in [0, 1, 2, 5] {
+            checkpoint.mark_done(chunk);
+        }
+        checkpoint.save(1234).unwrap();
+
+        let loaded = Checkpoint::load(path.clone()).unwrap();
+        assert_eq!(loaded.output_bytes(), 1234);
+        assert_eq!(loaded.pending(), [3..5, 6..10]);
+        assert!(loaded.validate(100, 10, 10).is_ok());
+        assert!(loaded.validate(101, 10, 10).is_err());
+        assert!(loaded.validate(100, 10, 11).is_err());
+
+        fs::write(&path, format!("{MAGIC}\nchunks 4\ndone 2-4\n")).unwrap();
+        assert!(Checkpoint::load(path.clone()).is_err());
+        fs::write(&path, "not a checkpoint\n").unwrap();
+        assert!(Checkpoint::load(path).is_err());
+    }
+}
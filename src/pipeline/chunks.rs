@@ -0,0 +1,103 @@
+use std::ops::Range;
+
+/// how many chunks of `target` bytes `len` bytes of input make
+pub fn count(len: usize, target: usize) -> usize {
+    len.div_ceil(target.max(1))
+}
+
+/// chunk `idx` of `data`: the lines that start within its `target` bytes. every
+/// line belongs to exactly one chunk, and finding a chunk only reads up to the
+/// end of the line crossing each of its boundaries. a line longer than `target`
+/// leaves the chunks it covers empty
+pub fn chunk(data: &[u8], target: usize, idx: usize) -> Range<usize> {
+    let target = target.max(1);
+    let start = idx.saturating_mul(target);
+    line_start(data, start)..line_start(data, start.saturating_add(target))
+}
+
+/// the first line start at or after `offset`
+fn line_start(data: &[u8], offset: usize) -> usize {
+    if offset == 0 || offset >= data.len() {
+        return offset.min(data.len());
+    }
+    data[offset - 1..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map_or(data.len(), |pos| offset + pos)
+}
+
+/// lines of a chunk without their `\n`. a trailing line break does not start
+/// another, empty line, and an empty chunk has no lines at all
+pub fn lines(chunk: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let body = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+    let count = if chunk.is_empty() { 0 } else { usize::MAX };
+    body.split(|b| *b == b'\n').take(count)
+}
+
+/// the chunks a run still has to do, as ranges of chunk indices. sequence
+/// numbers count through them in order
+#[derive(Clone, Debug)]
+pub struct Pending {
+    ranges: Vec<Range<usize>>,
+    /// sequence number of the first chunk of each range
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl Pending {
+    pub fn new(ranges: Vec<Range<usize>>) -> Self {
+        let mut starts = Vec::with_capacity(ranges.len());
+        let mut len = 0;
+        for range in &ranges {
+            starts.push(len);
+            len += range.len();
+        }
+        Self {
+            ranges,
+            starts,
+            len,
+        }
+    }
+
+    /// every one of `chunks` chunks
+    pub fn all(chunks: usize) -> Self {
+        Self::new(std::iter::once(0..chunks).collect())
+    }
+
+    /// the chunk behind sequence number `seq`
+    pub fn chunk(&self, seq: usize) -> Option<usize> {
+        if seq >= self.len {
+            return None;
+        }
+        let idx = self.starts.partition_point(|start| *start <= seq) - 1;
+        Some(self.ranges[idx].start + (seq - self.starts[idx]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_every_line_into_exactly_one_chunk() {
+        let data = b"a\nbb\na-very-long-line\n\nc\nlast";
+        for target in [1, 3, 4, 7, 64] {
+            let chunks: Vec<Range<usize>> = (0..count(data.len(), target))
+                .map(|idx| chunk(data, target, idx))
+                .collect();
+            let mut next = 0;
+            for range in &chunks {
+                assert_eq!(range.start, next, "target {target}");
+                next = range.end;
+            }
+            assert_eq!(next, data.len(), "target {target}");
+            let lines: Vec<&[u8]> = chunks
+                .iter()
+                .flat_map(|range| lines(&data[range.clone()]))
+                .collect();
+            let expected: Vec<&[u8]> = vec![b"a", b"bb", b"a-very-long-line", b"", b"c", b"last"];
+            assert_eq!(lines, expected, "target {target}");
+        }
+        assert_eq!(count(0, 4), 0);
+    }
+}
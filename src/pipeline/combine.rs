@@ -0,0 +1,152 @@
+use anyhow::{Context, Result};
+use log::info;
+use std::collections::BTreeMap;
+
+use super::source::open_input;
+use super::stages::{run_chain, Transform};
+
+/// the right-hand list of the combine subcommand, expanded by its own
+/// transforms and held in memory. the cross product is only ever streamed
+pub struct Combination {
+    right: Vec<String>,
+    separators: Vec<String>,
+    /// one representative right-hand word per byte length, with how many share it
+    by_length: BTreeMap<usize, (String, u128)>,
+}
+
+impl Combination {
+    /// no separators means the words are joined directly. the whole
+    /// transformed list is kept in memory, so its size is logged
+    pub fn load(
+        path: &str,
+        transforms: &[Box<dyn Transform>],
+        separators: Vec<String>,
+    ) -> Result<Self> {
+        let mut reader = open_input(path)?;
+        let mut right = Vec::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .with_context(|| format!("failed to read {path}"))?;
+            if read == 0 {
+                break;
+            }
+            let bytes = line.strip_suffix(b"\n").unwrap_or(&line);
+            let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+            // like the left side, lines that are not UTF-8 are skipped
+            if let Ok(word) = std::str::from_utf8(bytes) {
+                run_chain(transforms, word.to_owned(), &mut |variant| {
+                    right.push(variant);
+                    Ok(())
+                })?;
+            }
+        }
+        info!(
+            "combining with {} right-hand words ({} bytes) from {path}",
+            right.len(),
+            right.iter().map(String::len).sum::<usize>()
+        );
+        let mut by_length: BTreeMap<usize, (String, u128)> = BTreeMap::new();
+        for word in &right {
+            by_length
+                .entry(word.len())
+                .or_insert_with(|| (word.clone(), 0))
+                .1 += 1;
+        }
+        let separators = if separators.is_empty() {
+            vec![String::new()]
+        } else {
+            separators
+        };
+        Ok(Self {
+            right,
+            separators,
+            by_length,
+        })
+    }
+
+    pub fn apply(&self, word: &str, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()> {
+        for separator in &self.separators {
+            for right in &self.right {
+                visit(format!("{word}{separator}{right}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn estimate(&self, word: &str) -> Vec<(String, u128)> {
+        self.separators
+            .iter()
+            .flat_map(|separator| {
+                self.by_length
+                    .values()
+                    .map(move |(right, count)| (format!("{word}{separator}{right}"), *count))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::options::PipelineCommand;
+    use super::super::stages::Chars;
+    use super::*;
+    use cemplox::character_combinations::CountConfig;
+    use std::io::Write;
+
+    fn combination(separators: &[&str]) -> Combination {
+        let mut right = tempfile::NamedTempFile::new().unwrap();
+        right.write_all(b"ab\r\nxyz\n").unwrap();
+        let transforms: Vec<Box<dyn Transform>> = vec![Box::new(Chars {
+            command: PipelineCommand::Count(CountConfig::new("12").append(1)),
+            followed: false,
+        })];
+        Combination::load(
+            right.path().to_str().unwrap(),
+            &transforms,
+            separators.iter().map(|s| s.to_string()).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn joins_every_word_with_the_transformed_right_list() {
+        let mut joined = Vec::new();
+        combination(&["-", "."])
+            .apply("w", &mut |candidate| {
+                joined.push(candidate);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            joined,
+            [
+                "w-ab", "w-ab1", "w-ab2", "w-xyz", "w-xyz1", "w-xyz2", "w.ab", "w.ab1", "w.ab2",
+                "w.xyz", "w.xyz1", "w.xyz2"
+            ]
+        );
+        let mut direct = Vec::new();
+        combination(&[])
+            .apply("w", &mut |candidate| {
+                direct.push(candidate);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(direct, ["wab", "wab1", "wab2", "wxyz", "wxyz1", "wxyz2"]);
+    }
+
+    #[test]
+    fn estimates_by_right_hand_length() {
+        let estimate = combination(&["-"]).estimate("w");
+        assert_eq!(
+            estimate,
+            [
+                ("w-ab".to_string(), 1),
+                ("w-ab1".to_string(), 3),
+                ("w-xyz1".to_string(), 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::{bail, Result};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use xz2::bufread::XzDecoder;
+use xz2::write::XzEncoder;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const SEVEN_ZIP_MAGIC: &[u8] = &[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c];
+
+/// bytes `detect` needs to see
+pub const MAGIC_LEN: usize = 6;
+
+/// output is compressed in frames of about this many bytes
+const FRAME_SIZE: usize = 1024 * 1024;
+
+/// frames each compressor thread may have queued per output
+const FRAMES_PER_THREAD: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Format {
+    /// the compression the data starts with, if any. `name` is only used in errors
+    pub fn detect(head: &[u8], name: &str) -> Result<Option<Format>> {
+        if head.starts_with(SEVEN_ZIP_MAGIC) {
+            bail!("{name} is a 7z archive, which can't be streamed: extract it or recompress it with gzip, zstd or xz");
+        }
+        Ok([
+            (GZIP_MAGIC, Format::Gzip),
+            (ZSTD_MAGIC, Format::Zstd),
+            (XZ_MAGIC, Format::Xz),
+        ]
+        .into_iter()
+        .find(|(magic, _)| head.starts_with(magic))
+        .map(|(_, format)| format))
+    }
+}
+
+/// decode `reader` if it starts with a known magic, otherwise pass it through.
+/// concatenated members, frames and streams are all read
+pub fn decode(mut reader: Box<dyn BufRead>, name: &str) -> Result<Box<dyn BufRead>> {
+    let head = reader.fill_buf()?;
+    let head = &head[..head.len().min(MAGIC_LEN)];
+    Ok(match Format::detect(head, name)? {
+        None => reader,
+        Some(Format::Gzip) => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Some(Format::Zstd) => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        Some(Format::Xz) => Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))),
+    })
+}
+
+/// `--compress` setting: `zstd`, `gzip:9`, ...
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    format: Format,
+    level: u32,
+}
+
+impl Compression {
+    /// compress one buffer into a self-contained gzip member, zstd frame or xz
+    /// stream. concatenated, they decode to the concatenated buffers
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let out = Vec::with_capacity(data.len() / 2);
+        let compressed = match self.format {
+            Format::Gzip => {
+                let mut encoder = GzEncoder::new(out, flate2::Compression::new(self.level));
+                encoder.write_all(data).and_then(|_| encoder.finish())
+            }
+            Format::Zstd => zstd::bulk::compress(data, self.level as i32),
+            Format::Xz => {
+                let mut encoder = XzEncoder::new(out, self.level);
+                encoder.write_all(data).and_then(|_| encoder.finish())
+            }
+        };
+        compressed.expect("compressing into memory does not fail")
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (name, level) = text.split_once(':').unwrap_or((text, ""));
+        let (format, default, levels) = match name {
+            "gzip" | "gz" => (Format::Gzip, 6, 0..=9),
+            "zstd" | "zst" => (Format::Zstd, 3, 1..=22),
+            "xz" => (Format::Xz, 6, 0..=9),
+            _ => {
+                return Err(format!(
+                    "unknown format '{name}', expected gzip, zstd or xz"
+                ))
+            }
+        };
+        let level = match level {
+            "" => default,
+            level => level
+                .parse()
+                .ok()
+                .filter(|level| levels.contains(level))
+                .ok_or_else(|| {
+                    format!(
+                        "{name} levels go from {} to {}, not '{level}'",
+                        levels.start(),
+                        levels.end()
+                    )
+                })?,
+        };
+        Ok(Self { format, level })
+    }
+}
+
+/// compresses frames on threads of its own, so whoever writes the output only
+/// hands them out and collects the results. the threads end with the last clone
+pub struct Compressor {
+    frames: Sender<(Vec<u8>, Sender<Vec<u8>>)>,
+    threads: usize,
+}
+
+impl Compressor {
+    pub fn new(compression: Compression, threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (frames, receiver) = unbounded::<(Vec<u8>, Sender<Vec<u8>>)>();
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            thread::spawn(move || {
+                for (frame, reply) in receiver {
+                    let _ = reply.send(compression.compress(&frame));
+                }
+            });
+        }
+        Self { frames, threads }
+    }
+}
+
+/// writes to `inner`, compressed in frames on the compressor's threads if
+/// there is one. frames reach `inner` in the order their bytes were written,
+/// and `flush` ends the current frame so everything so far can be read back
+pub struct Encoder<W: Write> {
+    inner: W,
+    compressor: Option<Arc<Compressor>>,
+    frame: Vec<u8>,
+    in_flight: VecDeque<Receiver<Vec<u8>>>,
+    written: u64,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W, compressor: Option<Arc<Compressor>>) -> Self {
+        Self {
+            inner,
+            compressor,
+            frame: Vec::new(),
+            in_flight: VecDeque::new(),
+            written: 0,
+        }
+    }
+
+    /// bytes handed to `inner`, after compression
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    fn send_frame(&mut self, compressor: &Compressor) -> io::Result<()> {
+        if self.frame.is_empty() {
+            return Ok(());
+        }
+        if self.in_flight.len() >= compressor.threads * FRAMES_PER_THREAD {
+            self.write_oldest()?;
+        }
+        let frame = std::mem::replace(&mut self.frame, Vec::with_capacity(FRAME_SIZE));
+        let (reply, result) = bounded(1);
+        compressor
+            .frames
+            .send((frame, reply))
+            .map_err(|_| compressor_gone())?;
+        self.in_flight.push_back(result);
+        Ok(())
+    }
+
+    fn write_oldest(&mut self) -> io::Result<()> {
+        let Some(result) = self.in_flight.pop_front() else {
+            return Ok(());
+        };
+        let data = result.recv().map_err(|_| compressor_gone())?;
+        self.inner.write_all(&data)?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+}
+
+fn compressor_gone() -> io::Error {
+    io::Error::other("a compressor thread stopped")
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.compressor.clone() {
+            Some(compressor) => {
+                self.frame.extend_from_slice(buf);
+                if self.frame.len() >= FRAME_SIZE {
+                    self.send_frame(&compressor)?;
+                }
+            }
+            None => {
+                self.inner.write_all(buf)?;
+                self.written += buf.len() as u64;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(compressor) = self.compressor.clone() {
+            self.send_frame(&compressor)?;
+            while !self.in_flight.is_empty() {
+                self.write_oldest()?;
+            }
+        }
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn lines(count: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|n| format!("word{n}\n").into_bytes())
+            .collect()
+    }
+
+    fn encode(compression: Compression, data: &[u8]) -> Vec<u8> {
+        let compressor = Arc::new(Compressor::new(compression, 2));
+        let mut encoder = Encoder::new(Vec::new(), Some(compressor));
+        for piece in data.chunks(4096) {
+            encoder.write_all(piece).unwrap();
+        }
+        encoder.flush().unwrap();
+        assert_eq!(encoder.written(), encoder.inner.len() as u64);
+        encoder.inner
+    }
+
+    fn decoded(data: Vec<u8>) -> Vec<u8> {
+        let mut out = Vec::new();
+        decode(Box::new(io::Cursor::new(data)), "test")
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn round_trips_every_format_across_frames() {
+        let data = lines(400_000);
+        assert!(data.len() > 3 * FRAME_SIZE);
+        for format in ["gzip", "zstd", "xz"] {
+            let compression: Compression = format.parse().unwrap();
+            let encoded = encode(compression, &data);
+            assert!(decoded(encoded) == data, "{format}");
+        }
+    }
+
+    #[test]
+    fn small_writes_share_one_frame() {
+        let data = lines(50_000);
+        assert!(data.len() < FRAME_SIZE);
+        for format in ["gzip", "zstd", "xz"] {
+            let compression: Compression = format.parse().unwrap();
+            assert!(
+                encode(compression, &data) == compression.compress(&data),
+                "{format}"
+            );
+        }
+    }
+
+    #[test]
+    fn passes_bytes_through_without_a_compressor() {
+        let mut encoder = Encoder::new(Vec::new(), None);
+        encoder.write_all(b"word\n").unwrap();
+        encoder.flush().unwrap();
+        assert_eq!(encoder.written(), 5);
+        assert_eq!(decoded(encoder.inner), b"word\n");
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const SHARD_COUNT: usize = 64;
+
+/// run-wide set of emitted candidates shared by every worker. it lives in
+/// memory only and is not saved with the checkpoint, so a resumed run starts
+/// with an empty set
+pub enum Dedup {
+    Exact(ShardedSet),
+    Bloom(BloomFilter),
+}
+
+impl Dedup {
+    pub fn exact() -> Self {
+        Dedup::Exact(ShardedSet::new())
+    }
+
+    pub fn bloom(capacity: u64, fp_rate: f64) -> Self {
+        Dedup::Bloom(BloomFilter::new(capacity, fp_rate))
+    }
+
+    /// true if the candidate has not been seen before
+    pub fn insert(&self, candidate: &str) -> bool {
+        match self {
+            Dedup::Exact(set) => set.insert(candidate),
+            Dedup::Bloom(filter) => filter.insert(candidate),
+        }
+    }
+}
+
+pub struct ShardedSet {
+    hasher: RandomState,
+    shards: Vec<Mutex<HashSet<Box<str>>>>,
+}
+
+impl ShardedSet {
+    fn new() -> Self {
+        Self {
+            hasher: RandomState::new(),
+            shards: (0..SHARD_COUNT).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    fn insert(&self, candidate: &str) -> bool {
+        let shard = self.hasher.hash_one(candidate) as usize % self.shards.len();
+        let mut set = self.shards[shard].lock().expect("dedup shard poisoned");
+        if set.contains(candidate) {
+            return false;
+        }
+        set.insert(candidate.into())
+    }
+}
+
+/// lock-free bloom filter. a false positive drops a candidate that was never
+/// written, at roughly `fp_rate` once `capacity` candidates have been inserted
+pub struct BloomFilter {
+    hasher: RandomState,
+    bits: Vec<AtomicU64>,
+    bit_count: u64,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    fn new(capacity: u64, fp_rate: f64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        let fp_rate = fp_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bit_count = (-(capacity * fp_rate.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let hashes = ((bit_count as f64 / capacity) * ln2)
+            .round()
+            .clamp(1.0, 32.0) as u32;
+        let words = bit_count.div_ceil(64) as usize;
+        Self {
+            hasher: RandomState::new(),
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            bit_count: words as u64 * 64,
+            hashes,
+        }
+    }
+
+    /// double hashing: bit `i` is `h1 + i * h2`
+    fn insert(&self, candidate: &str) -> bool {
+        let h1 = self.hasher.hash_one(candidate);
+        let h2 = splitmix64(h1) | 1;
+        let mut fresh = false;
+        for i in 0..self.hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.bit_count;
+            let mask = 1u64 << (bit % 64);
+            let previous = self.bits[(bit / 64) as usize].fetch_or(mask, Ordering::Relaxed);
+            fresh |= previous & mask == 0;
+        }
+        fresh
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_set_drops_only_repeats() {
+        let dedup = Dedup::exact();
+        let words: Vec<String> = (0..10_000).map(|n| format!("w{n}")).collect();
+        assert!(words.iter().all(|word| dedup.insert(word)));
+        assert!(words.iter().all(|word| !dedup.insert(word)));
+        assert!(dedup.insert("fresh"));
+    }
+
+    #[test]
+    fn sizes_bloom_filter_from_capacity_and_rate() {
+        let filter = BloomFilter::new(10_000, 0.01);
+        // -(n ln p) / ln2^2 = 95851 bits, rounded up to whole words
+        assert_eq!(filter.bit_count, 95_872);
+        assert_eq!(filter.hashes, 7);
+        let tiny = BloomFilter::new(0, 0.9);
+        assert_eq!(tiny.bit_count, 64);
+        assert_eq!(tiny.hashes, 32);
+    }
+
+    #[test]
+    fn bloom_filter_stays_near_its_fp_rate() {
+        let dedup = Dedup::bloom(10_000, 0.01);
+        for n in 0..10_000 {
+            dedup.insert(&format!("seen{n}"));
+        }
+        assert!((0..10_000).all(|n| !dedup.insert(&format!("seen{n}"))));
+        let false_positives = (0..2_000)
+            .filter(|n| !dedup.insert(&format!("new{n}")))
+            .count();
+        // the probes fill the filter past capacity as they go, so the rate
+        // they see creeps up towards 2%. allow some slack on top of that
+        assert!(false_positives < 100, "{false_positives} false positives");
+    }
+}
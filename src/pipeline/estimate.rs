@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::metrics::Metrics;
+use super::options::PipelineOptions;
+use super::worker::WorkerState;
+use cemplox::character_combinations::Mask;
+
+/// candidates and output bytes (newlines included) a run would produce
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Estimate {
+    pub candidates: u128,
+    pub bytes: u128,
+}
+
+impl Estimate {
+    pub fn combine(self, other: Estimate) -> Estimate {
+        Estimate {
+            candidates: self.candidates.saturating_add(other.candidates),
+            bytes: self.bytes.saturating_add(other.bytes),
+        }
+    }
+
+    fn of_length(len: usize, count: u128) -> Estimate {
+        Estimate {
+            candidates: count,
+            bytes: count.saturating_mul(len as u128 + 1),
+        }
+    }
+}
+
+/// estimate one input line and report it as `candidates<TAB>bytes<TAB>line`
+pub fn estimate_raw_line(
+    line_bytes: &[u8],
+    options: &Arc<PipelineOptions>,
+    metrics: &Arc<Metrics>,
+    state: &mut WorkerState,
+    capacity: usize,
+) -> Estimate {
+    let line_bytes = line_bytes.strip_suffix(b"\r").unwrap_or(line_bytes);
+
+    match std::str::from_utf8(line_bytes) {
+        Ok(line) => {
+            metrics.record_line();
+            let estimate = estimate_line(line, options);
+            state.push_line(
+                &format!("{}\t{}\t{}", estimate.candidates, estimate.bytes, line),
+                capacity,
+            );
+            estimate
+        }
+        Err(_) => {
+            metrics.record_invalid();
+            Estimate::default()
+        }
+    }
+}
+
+/// a mask's keyspace is exact, reported as `candidates<TAB>bytes<TAB>mask`
+pub fn estimate_mask(mask: &Mask, state: &mut WorkerState, capacity: usize) -> Estimate {
+    let candidates = mask.keyspace();
+    let estimate = Estimate {
+        candidates,
+        bytes: mask.keyspace_bytes().saturating_add(candidates),
+    };
+    state.push_line(
+        &format!("{}\t{}\t{}", estimate.candidates, estimate.bytes, mask.text()),
+        capacity,
+    );
+    estimate
+}
+
+/// walk the transform chain with a small population of representative words,
+/// each standing for a number of candidates, instead of the candidates themselves
+pub fn estimate_line(line: &str, options: &PipelineOptions) -> Estimate {
+    let mut population = vec![(line.to_owned(), 1u128)];
+    for transform in &options.transforms {
+        let mut next: BTreeMap<String, u128> = BTreeMap::new();
+        for (word, count) in population {
+            for (variant, variants) in transform.estimate(&word) {
+                let entry = next.entry(variant).or_default();
+                *entry = entry.saturating_add(count.saturating_mul(variants));
+            }
+        }
+        population = next.into_iter().collect();
+    }
+    population
+        .into_iter()
+        .map(|(word, count)| Estimate::of_length(word.len(), count))
+        .fold(Estimate::default(), Estimate::combine)
+}
@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexSet};
+use std::str::FromStr;
+
+use crate::cli::Cli;
+use cemplox::character_combinations::LengthUnit;
+
+/// policy checks every candidate has to pass before it is written
+pub struct Filter {
+    include: Option<RegexSet>,
+    exclude: Option<RegexSet>,
+    classes: Vec<ClassRequirement>,
+    min_len: Option<usize>,
+    max_len: Option<usize>,
+    unit: LengthUnit,
+}
+
+impl Filter {
+    /// `None` when no filter option is given, so the hot path skips it entirely
+    pub fn from_cli(cli: &Cli) -> Result<Option<Self>> {
+        if cli.include_regex.is_empty()
+            && cli.exclude_regex.is_empty()
+            && cli.require_classes.is_empty()
+            && cli.min_len.is_none()
+            && cli.max_len.is_none()
+        {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            include: regex_set(&cli.include_regex).context("invalid --include-regex")?,
+            exclude: regex_set(&cli.exclude_regex).context("invalid --exclude-regex")?,
+            classes: cli.require_classes.clone(),
+            min_len: cli.min_len,
+            max_len: cli.max_len,
+            unit: cli.length_unit,
+        }))
+    }
+
+    /// lengths are counted in --length-unit. a candidate has to match at
+    /// least one include pattern and none of the exclude patterns
+    pub fn accepts(&self, candidate: &str) -> bool {
+        if self.min_len.is_some() || self.max_len.is_some() {
+            let len = self.unit.measure(candidate);
+            if self.min_len.is_some_and(|min| len < min)
+                || self.max_len.is_some_and(|max| len > max)
+            {
+                return false;
+            }
+        }
+        if !self.classes.iter().all(|req| req.is_met_by(candidate)) {
+            return false;
+        }
+        if self
+            .include
+            .as_ref()
+            .is_some_and(|set| !set.is_match(candidate))
+        {
+            return false;
+        }
+        !self
+            .exclude
+            .as_ref()
+            .is_some_and(|set| set.is_match(candidate))
+    }
+}
+
+fn regex_set(patterns: &[String]) -> Result<Option<RegexSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    // compile one by one first so the error names the offending pattern
+    for pattern in patterns {
+        Regex::new(pattern).with_context(|| format!("pattern '{pattern}'"))?;
+    }
+    Ok(Some(RegexSet::new(patterns)?))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharClass {
+    Lower,
+    Upper,
+    Digit,
+    Special,
+}
+
+impl CharClass {
+    /// letters of any script count by their case, but only 0-9 are digits so
+    /// `½` or `Ⅻ` never satisfy a digit policy. special is anything that is
+    /// neither a letter nor a digit, spaces and other numerals included
+    fn contains(self, ch: char) -> bool {
+        match self {
+            CharClass::Lower => ch.is_lowercase(),
+            CharClass::Upper => ch.is_uppercase(),
+            CharClass::Digit => ch.is_ascii_digit(),
+            CharClass::Special => !ch.is_alphabetic() && !ch.is_ascii_digit(),
+        }
+    }
+}
+
+/// `digit` or `digit:2`: at least that many chars of the class
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClassRequirement {
+    class: CharClass,
+    min: usize,
+}
+
+impl ClassRequirement {
+    fn is_met_by(self, candidate: &str) -> bool {
+        candidate
+            .chars()
+            .filter(|&ch| self.class.contains(ch))
+            .count()
+            >= self.min
+    }
+}
+
+impl FromStr for ClassRequirement {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (name, min) = match text.split_once(':') {
+            Some((name, min)) => (
+                name,
+                min.parse()
+                    .map_err(|_| format!("'{min}' is not a count in '{text}'"))?,
+            ),
+            None => (text, 1),
+        };
+        let class = match name {
+            "lower" => CharClass::Lower,
+            "upper" => CharClass::Upper,
+            "digit" => CharClass::Digit,
+            "special" => CharClass::Special,
+            _ => {
+                return Err(format!(
+                    "unknown class '{name}', expected lower, upper, digit or special"
+                ))
+            }
+        };
+        Ok(Self { class, min })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(classes: &[&str]) -> Filter {
+        Filter {
+            include: regex_set(&["^p".to_string()]).unwrap(),
+            exclude: regex_set(&["word".to_string()]).unwrap(),
+            classes: classes.iter().map(|c| c.parse().unwrap()).collect(),
+            min_len: Some(4),
+            max_len: Some(8),
+            unit: LengthUnit::Chars,
+        }
+    }
+
+    #[test]
+    fn parses_class_requirements() {
+        let digits: ClassRequirement = "digit:2".parse().unwrap();
+        assert_eq!(
+            digits,
+            ClassRequirement {
+                class: CharClass::Digit,
+                min: 2
+            }
+        );
+        assert_eq!("upper".parse::<ClassRequirement>().unwrap().min, 1);
+        assert!("digit:two".parse::<ClassRequirement>().is_err());
+        assert!("emoji".parse::<ClassRequirement>().is_err());
+    }
+
+    #[test]
+    fn accepts_only_candidates_meeting_every_check() {
+        let filter = filter(&["digit:2", "special"]);
+        assert!(filter.accepts("pa55!"));
+        assert!(!filter.accepts("pa5!"));
+        assert!(!filter.accepts("pa\u{bd}5!"));
+        assert!(!filter.accepts("xa55!"));
+        assert!(!filter.accepts("pword55!"));
+        assert!(!filter.accepts("p5!"));
+        assert!(!filter.accepts("pa55!pa55!"));
+        assert!(filter.accepts("p\u{bd}55"));
+    }
+}
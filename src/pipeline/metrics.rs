@@ -5,6 +5,11 @@ pub struct Metrics {
     lines: AtomicU64,
     variants: AtomicU64,
     invalid: AtomicU64,
+    duplicates: AtomicU64,
+    filtered: AtomicU64,
+    /// sanitize candidates outside its length bounds
+    too_short: AtomicU64,
+    too_long: AtomicU64,
 }
 
 impl Metrics {
@@ -20,6 +25,22 @@ impl Metrics {
         self.invalid.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn record_duplicate(&self) {
+        self.duplicates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_filtered(&self) {
+        self.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_too_short(&self) {
+        self.too_short.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_too_long(&self) {
+        self.too_long.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn lines(&self) -> u64 {
         self.lines.load(Ordering::Relaxed)
     }
@@ -31,4 +52,20 @@ impl Metrics {
     pub fn invalid(&self) -> u64 {
         self.invalid.load(Ordering::Relaxed)
     }
+
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    pub fn filtered(&self) -> u64 {
+        self.filtered.load(Ordering::Relaxed)
+    }
+
+    pub fn too_short(&self) -> u64 {
+        self.too_short.load(Ordering::Relaxed)
+    }
+
+    pub fn too_long(&self) -> u64 {
+        self.too_long.load(Ordering::Relaxed)
+    }
 }
@@ -1,31 +1,51 @@
+mod checkpoint;
+mod chunks;
+mod combine;
+mod compress;
+mod dedup;
+mod estimate;
+mod filter;
 mod metrics;
 mod options;
+mod scheduler;
+mod source;
+mod split;
 mod stages;
 mod worker;
 mod watchdog;
+mod writer;
 
-use crate::cli::Cli;
-use anyhow::{Context, Result};
+use crate::cli::{Cli, Commands};
+use anyhow::{bail, Context, Result};
 use crossbeam_channel::bounded;
-use env_logger;
-use log::info;
-use memmap2::Mmap;
-use rayon::prelude::*;
-use rayon::slice::ParallelSlice;
+use log::{info, warn};
 use rayon::ThreadPoolBuilder;
-use std::fs::File;
-use std::io::{self, BufWriter, Write};
-use std::sync::Arc;
-use std::thread;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use checkpoint::Checkpoint;
+use compress::Compressor;
+use chunks::Pending;
+use dedup::Dedup;
+use estimate::Estimate;
 use metrics::Metrics;
 use options::PipelineOptions;
-use stages::process_chunk;
+use scheduler::{run_jobs, OrderWindow};
+use source::Source;
 use worker::WorkerState;
+use split::SplitConfig;
+use writer::{create_writer, resume_writer, spawn_writer, split_writer, WriterProgress};
+
+pub use compress::Compression;
+pub use filter::ClassRequirement;
+pub use split::parse_size;
 
 const BUFFER_CAPACITY: usize = 64 * 1024;
 const CHANNEL_MULTIPLIER: usize = 4;
+const CHUNK_SIZE: usize = 4 * 1024;
+const MASK_JOB_SIZE: u128 = 64 * 1024;
+const REORDER_MULTIPLIER: usize = 4;
+const REORDER_BYTES: u64 = 256 * 1024 * 1024;
 
 pub fn run(cli: Cli) -> Result<()> {
     std::env::set_var("RUST_LOG", &cli.log_level);
@@ -35,76 +55,226 @@ pub fn run(cli: Cli) -> Result<()> {
         .build_global()?;
     info!("parallel processing with {} threads", cli.max_threads);
 
-    let options = Arc::new(PipelineOptions::from_cli(&cli));
     let metrics = Arc::new(Metrics::default());
+    let options = Arc::new(PipelineOptions::from_cli(&cli, &metrics)?);
     let watchdog_guard = cli.max_rss_mb.map(watchdog::spawn);
+    let dedup = cli.dedup.then(|| {
+        Arc::new(match cli.dedup_fp_rate {
+            Some(fp_rate) => Dedup::bloom(cli.dedup_capacity, fp_rate),
+            None => Dedup::exact(),
+        })
+    });
 
-    let input_file = File::open(&cli.file)
-        .with_context(|| format!("failed to open input file {}", cli.file))?;
-    let mmap = unsafe { Mmap::map(&input_file)? };
+    let source = match &cli.command {
+        Some(Commands::Mask(_)) if !cli.file.is_empty() => {
+            bail!("the mask command does not read --file")
+        }
+        Some(Commands::Mask(args)) => {
+            let job_size = if cli.dry_run { u128::MAX } else { MASK_JOB_SIZE };
+            Source::masks(args, job_size)?
+        }
+        _ if cli.file.is_empty() => bail!("--file is required unless the mask command is used"),
+        _ => Source::lines(&cli.file, CHUNK_SIZE)?,
+    };
 
-    let writer = create_writer(cli.out_file.as_deref())
-        .with_context(|| "failed to create output destination".to_string())?;
+    let split = SplitConfig {
+        bytes: cli.split_bytes,
+        lines: cli.split_lines,
+        by_length: cli.split_by_length,
+        unit: cli.length_unit,
+    };
+    let split = split.is_enabled().then_some(split);
+    if split.is_some() && cli.checkpoint.is_some() {
+        bail!("--checkpoint can't be combined with split output");
+    }
+
+    let checkpoint = match &cli.checkpoint {
+        Some(path) if cli.resume => {
+            let (_, input_len, chunk_size) = source.identity()?;
+            let checkpoint = Checkpoint::load(path.clone())?;
+            checkpoint.validate(input_len, chunk_size, source.len())?;
+            info!(
+                "resuming from {}: {} of {} chunks left",
+                path.display(),
+                checkpoint.remaining(),
+                source.len()
+            );
+            if cli.dedup {
+                warn!("--dedup starts empty on --resume, so it can't drop candidates written before");
+            }
+            Some(checkpoint)
+        }
+        Some(path) => {
+            let (input, input_len, chunk_size) = source.identity()?;
+            Some(Checkpoint::new(
+                path.clone(),
+                &input,
+                input_len,
+                chunk_size,
+                source.len(),
+            ))
+        }
+        None => None,
+    };
+    let pending = match &checkpoint {
+        Some(checkpoint) => Pending::new(checkpoint.pending()),
+        None => Pending::all(source.len()),
+    };
+
+    let compressor = cli
+        .compress
+        .map(|compression| Arc::new(Compressor::new(compression, cli.max_threads)));
+    let writer = match (&checkpoint, cli.out_file.as_deref(), split) {
+        (Some(checkpoint), Some(path), _) if cli.resume => {
+            resume_writer(path, checkpoint, compressor)
+        }
+        (_, Some(path), Some(split)) => split_writer(path, split, compressor),
+        (_, path, _) => create_writer(path, compressor),
+    }
+    .with_context(|| "failed to create output destination".to_string())?;
 
     let channel_depth = cli.max_threads.max(1) * CHANNEL_MULTIPLIER;
     let (sender, receiver) = bounded(channel_depth.max(2));
-    let writer_thread = spawn_writer(writer, receiver);
+    // a checkpoint records the output length after its last done chunk, which
+    // only covers exactly the done chunks when they are written in order
+    let ordered = cli.ordered || checkpoint.is_some();
+    let window = ordered.then(|| {
+        let size = cli
+            .reorder_window
+            .unwrap_or(cli.max_threads * REORDER_MULTIPLIER);
+        let max_bytes = cli.reorder_bytes.unwrap_or(REORDER_BYTES);
+        Arc::new(OrderWindow::new(size, max_bytes as usize))
+    });
 
+    let capacity = BUFFER_CAPACITY;
     let start = Instant::now();
-    let options_ref = Arc::clone(&options);
-    let metrics_ref = Arc::clone(&metrics);
-    let sender_clone = sender.clone();
-
-    mmap.par_split(|b| *b == b'\n')
-        .for_each_init(
-            move || WorkerState::new(sender_clone.clone(), BUFFER_CAPACITY),
-            move |state, chunk| {
-                process_chunk(
-                    chunk,
-                    &options_ref,
-                    &metrics_ref,
-                    state,
-                    BUFFER_CAPACITY,
-                );
+    // dry runs can't take --out-file or --checkpoint, so their estimates only
+    // ever go to stdout
+    if cli.dry_run {
+        if dedup.is_some() {
+            warn!("dry run estimates do not account for --dedup");
+        }
+        if options.filter.is_some() {
+            warn!("dry run estimates do not account for candidate filters");
+        }
+        let writer_thread = spawn_writer(
+            writer,
+            receiver,
+            WriterProgress {
+                checkpoint: None,
+                checkpoint_interval: Duration::MAX,
+                chunks: pending.clone(),
+                window: window.clone(),
             },
         );
+        let total = Mutex::new(Estimate::default());
+        let (jobs, reader_thread) = source.jobs(pending, channel_depth);
+        let work_result = run_jobs(
+            cli.max_threads,
+            window.as_deref(),
+            capacity,
+            || jobs.next(),
+            || WorkerState::new(sender.clone(), capacity, None, window.clone()),
+            |job, state| {
+                let estimate = source.estimate(job, &options, &metrics, state, capacity);
+                let mut total = total.lock().expect("estimate total poisoned");
+                *total = total.combine(estimate);
+                Ok(())
+            },
+        );
+        // a reader still feeding stopped workers finishes once the jobs are gone
+        drop(jobs);
+        drop(sender);
+        let reader_result =
+            reader_thread.map(|reader| reader.join().expect("reader thread panicked"));
+        let writer_result = writer_thread.join().expect("writer thread panicked");
+        if let Some(guard) = watchdog_guard {
+            guard.stop();
+        }
+        work_result?;
+        reader_result.transpose()?;
+        writer_result?;
+        write_years(&cli, &options)?;
+        let estimate = total.into_inner().expect("estimate total poisoned");
+        info!(
+            "dry run: {} lines ({} invalid) -> {} candidates, {} bytes estimated in {:.2?}",
+            metrics.lines(),
+            metrics.invalid(),
+            estimate.candidates,
+            estimate.bytes,
+            start.elapsed()
+        );
+        log_rejections(&metrics);
+        return Ok(());
+    }
+
+    let writer_thread = spawn_writer(
+        writer,
+        receiver,
+        WriterProgress {
+            checkpoint,
+            checkpoint_interval: Duration::from_secs(cli.checkpoint_interval),
+            chunks: pending.clone(),
+            window: window.clone(),
+        },
+    );
+    let (jobs, reader_thread) = source.jobs(pending, channel_depth);
+    let work_result = run_jobs(
+        cli.max_threads,
+        window.as_deref(),
+        capacity,
+        || jobs.next(),
+        || {
+            WorkerState::new(sender.clone(), capacity, dedup.clone(), window.clone())
+        },
+        |job, state| source.process(job, &options, &metrics, state, capacity),
+    );
 
+    drop(jobs);
     drop(sender);
+    let reader_result =
+        reader_thread.map(|reader| reader.join().expect("reader thread panicked"));
     let writer_result = writer_thread.join().expect("writer thread panicked");
     if let Some(guard) = watchdog_guard {
         guard.stop();
     }
+    work_result?;
+    reader_result.transpose()?;
     writer_result?;
+    write_years(&cli, &options)?;
 
     let elapsed = start.elapsed();
     info!(
-        "processed {} lines ({} invalid) -> {} variants in {:.2?}",
+        "processed {} lines ({} invalid) -> {} variants ({} duplicates dropped, {} filtered out) in {:.2?}",
         metrics.lines(),
         metrics.invalid(),
         metrics.variants(),
+        metrics.duplicates(),
+        metrics.filtered(),
         elapsed
     );
+    log_rejections(&metrics);
     Ok(())
 }
 
-fn create_writer(path: Option<&str>) -> Result<Box<dyn Write + Send>> {
-    if let Some(path) = path {
-        let file = File::create(path)?;
-        Ok(Box::new(BufWriter::new(file)))
-    } else {
-        Ok(Box::new(BufWriter::new(io::stdout())))
-    }
+/// one harvested release year per line, oldest first
+fn write_years(cli: &Cli, options: &PipelineOptions) -> Result<()> {
+    let (Some(path), Some(years)) = (&cli.harvest_years, &options.years) else {
+        return Ok(());
+    };
+    let years = years.lock().expect("year set poisoned");
+    let text: String = years.iter().map(|year| format!("{year}\n")).collect();
+    std::fs::write(path, text).with_context(|| format!("failed to write {path}"))?;
+    info!("harvested {} release years into {path}", years.len());
+    Ok(())
 }
 
-fn spawn_writer(
-    mut writer: Box<dyn Write + Send>,
-    receiver: crossbeam_channel::Receiver<Vec<u8>>,
-) -> thread::JoinHandle<Result<()>> {
-    thread::spawn(move || {
-        for buffer in receiver {
-            writer.write_all(&buffer)?;
-        }
-        writer.flush()?;
-        Ok(())
-    })
+fn log_rejections(metrics: &Metrics) {
+    if metrics.too_short() > 0 || metrics.too_long() > 0 {
+        info!(
+            "sanitize dropped {} candidates below and {} above its length bounds",
+            metrics.too_short(),
+            metrics.too_long()
+        );
+    }
 }
@@ -1,60 +1,484 @@
-use crate::cli::{Cli, Commands};
+use super::combine::Combination;
+use super::filter::Filter;
+use super::metrics::Metrics;
+use super::stages::{Case, Chars, Leet, Phrase, Rules, Sanitize, Transform, EXACT_ESTIMATE_LIMIT};
+use crate::cli::{Cli, Commands, Stage};
+use anyhow::{bail, Context, Result};
+use cemplox::character_combinations::{CountConfig, LengthConfig, Mask, SpillConfig};
+use cemplox::leet_combinations::LeetTable;
+use cemplox::phrase_combinations::PhraseStyle;
+use cemplox::rules;
+use cemplox::sanitize::{Metadata, SanitizeConfig};
+use log::warn;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
 
-#[derive(Clone)]
 pub struct PipelineOptions {
-    pub sanitize: bool,
-    pub case: bool,
-    pub leet: bool,
-    pub chars: String,
-    pub command: PipelineCommand,
-    pub case_max_changes: Option<usize>,
-    pub leet_max_substitutions: Option<usize>,
+    /// every input line runs through these in order
+    pub transforms: Vec<Box<dyn Transform>>,
+    pub filter: Option<Filter>,
+    /// release years the sanitize stage found, with --harvest-years
+    pub years: Option<Arc<Mutex<BTreeSet<u16>>>>,
 }
 
 impl PipelineOptions {
-    pub fn from_cli(cli: &Cli) -> Self {
+    pub fn from_cli(cli: &Cli, metrics: &Arc<Metrics>) -> Result<Self> {
         let command = match &cli.command {
             Some(Commands::Length(opts)) => PipelineCommand::Length {
-                min: opts.min,
-                max: opts.max,
-                append: opts.append,
-                prepend: opts.prepend,
-                insert: opts.insert,
-                dedup: !opts.skip_dedup,
-            },
-            Some(Commands::Count(opts)) => PipelineCommand::Count {
-                append: opts.append,
-                prepend: opts.prepend,
-                insert: opts.insert,
+                config: LengthConfig::new(cli.chars.as_str())
+                    .min(opts.min)
+                    .max(opts.max)
+                    .append(opts.append)
+                    .prepend(opts.prepend)
+                    .insert(opts.insert)
+                    .unit(cli.length_unit)
+                    .dedup(!opts.skip_dedup),
+                spill: cli.tempfile_mode.then(|| SpillConfig {
+                    threshold: cli.tempfile_threshold,
+                    dir: cli.tempfile_dir.clone(),
+                }),
             },
-            None => PipelineCommand::None,
+            Some(Commands::Count(opts)) => PipelineCommand::Count(
+                CountConfig::new(cli.chars.as_str())
+                    .append(opts.append)
+                    .prepend(opts.prepend)
+                    .insert(opts.insert)
+                    .unit(cli.length_unit),
+            ),
+            Some(Commands::Hybrid(opts)) => {
+                let custom = opts.charsets.to_vec();
+                let parse = |mask: &Option<String>| match mask {
+                    Some(mask) => Mask::parse(mask, &custom),
+                    None => Ok(Mask::default()),
+                };
+                PipelineCommand::Hybrid {
+                    prepend: parse(&opts.prepend)?,
+                    append: parse(&opts.append)?,
+                }
+            }
+            // the right side needs the transforms, so it is loaded below
+            Some(Commands::Combine(_)) | Some(Commands::Mask(_)) | None => PipelineCommand::None,
+        };
+        check_mask_flags(cli)?;
+        let stages = if cli.pipeline.is_empty() {
+            default_stages(cli)
+        } else {
+            check_stage_flags(cli)?;
+            cli.pipeline.clone()
+        };
+        let chars_followed = stages[..stages.len().saturating_sub(1)].contains(&Stage::Chars);
+        if cli.dry_run && chars_followed {
+            warn!(
+                "stages after chars only see samples of words with more than \
+                 {EXACT_ESTIMATE_LIMIT} chars variants, so their dry run estimates are approximate"
+            );
+        }
+        let combine = matches!(cli.command, Some(Commands::Combine(_)));
+        if (combine || !matches!(command, PipelineCommand::None)) && !stages.contains(&Stage::Chars)
+        {
+            bail!("--pipeline needs a chars stage to run the subcommand");
+        }
+
+        let rules = match &cli.rules {
+            Some(path) => rules::load_rules(path)?,
+            None if stages.contains(&Stage::Rules) => bail!("the rules stage needs --rules"),
+            None => Vec::new(),
         };
-        Self {
-            sanitize: cli.sanitize,
-            case: cli.case,
-            leet: cli.leet,
-            chars: cli.chars.clone(),
-            command,
-            case_max_changes: cli.case_max_changes,
-            leet_max_substitutions: cli.leet_max_substitutions,
+        let mut leet_table = LeetTable::preset(cli.leet_preset);
+        if let Some(path) = &cli.leet_table {
+            leet_table.extend(LeetTable::load(path)?);
+        }
+        let mut metadata = Metadata::default();
+        for pack in &cli.metadata_packs {
+            metadata.extend(Metadata::pack(*pack));
         }
+        if let Some(path) = &cli.metadata_config {
+            metadata.extend(Metadata::load(path)?);
+        }
+        if cli.harvest_years.is_some() && !stages.contains(&Stage::Sanitize) {
+            bail!("--harvest-years needs the sanitize stage");
+        }
+        let years = match &cli.harvest_years {
+            Some(path) => Some(Arc::new(Mutex::new(harvested_years(path, cli.resume)?))),
+            None => None,
+        };
+        let phrase_styles = if cli.phrase_styles.is_empty() {
+            PhraseStyle::ALL.to_vec()
+        } else {
+            cli.phrase_styles.clone()
+        };
+        let build = |stages: &[Stage], command: &PipelineCommand| -> Vec<Box<dyn Transform>> {
+            stages
+                .iter()
+                .enumerate()
+                .map(|(idx, stage)| -> Box<dyn Transform> {
+                    match stage {
+                        Stage::Sanitize => {
+                            let (min_len, max_len) =
+                                sanitize_bounds(cli, &stages[idx + 1..], command, &leet_table);
+                            Box::new(Sanitize {
+                                config: SanitizeConfig::new()
+                                    .min_len(min_len)
+                                    .max_len(max_len)
+                                    .unit(cli.length_unit)
+                                    .transliterate(!cli.no_transliterate)
+                                    .keep_unicode(cli.keep_unicode)
+                                    .year_variants(cli.year_variants)
+                                    .metadata(metadata.clone()),
+                                years: years.clone(),
+                                metrics: metrics.clone(),
+                            })
+                        }
+                        Stage::Phrase => Box::new(Phrase {
+                            styles: phrase_styles.clone(),
+                        }),
+                        Stage::Case => Box::new(Case {
+                            modes: cli.case_mode.clone(),
+                            max_changes: cli.case_max_changes,
+                        }),
+                        Stage::Leet => Box::new(Leet {
+                            table: leet_table.clone(),
+                            max_substitutions: cli.leet_max_substitutions,
+                        }),
+                        Stage::Rules => Box::new(Rules {
+                            rules: rules.clone(),
+                        }),
+                        Stage::Chars => Box::new(Chars {
+                            command: command.clone(),
+                            followed: idx + 1 < stages.len(),
+                        }),
+                    }
+                })
+                .collect()
+        };
+        let command = match &cli.command {
+            Some(Commands::Combine(args)) => {
+                let right_stages = if args.right_pipeline.is_empty() {
+                    vec![Stage::Chars]
+                } else {
+                    args.right_pipeline.clone()
+                };
+                // the right side is read in full before any left line, so
+                // stdin can only feed one of them
+                if args.right_file == "-" && cli.file.iter().any(|path| path == "-") {
+                    bail!("combine can't read both sides from stdin");
+                }
+                if right_stages.contains(&Stage::Rules) && cli.rules.is_none() {
+                    bail!("the rules stage needs --rules");
+                }
+                let right_count = PipelineCommand::Count(
+                    CountConfig::new(cli.chars.as_str())
+                        .append(args.right_append)
+                        .prepend(args.right_prepend)
+                        .insert(args.right_insert)
+                        .unit(cli.length_unit),
+                );
+                let right = build(&right_stages, &right_count);
+                PipelineCommand::Combine(Arc::new(Combination::load(
+                    &args.right_file,
+                    &right,
+                    args.separator.clone(),
+                )?))
+            }
+            _ => command,
+        };
+        let transforms = build(&stages, &command);
+        Ok(Self {
+            transforms,
+            filter: Filter::from_cli(cli)?,
+            years,
+        })
     }
 }
 
+/// sanitize, phrase, case, leet, rules and chars, each only when its flag is given
+fn default_stages(cli: &Cli) -> Vec<Stage> {
+    [
+        (Stage::Sanitize, cli.sanitize),
+        (Stage::Phrase, cli.phrases),
+        (Stage::Case, cli.case),
+        (Stage::Leet, cli.leet),
+        (Stage::Rules, cli.rules.is_some()),
+        (Stage::Chars, true),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(stage, _)| stage)
+    .collect()
+}
+
+/// on --resume the years file already holds what the earlier run found, so
+/// the new ones are added to it rather than replacing it
+fn harvested_years(path: &str, resume: bool) -> Result<BTreeSet<u16>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) if resume => text,
+        Err(err) if resume && err.kind() != io::ErrorKind::NotFound => {
+            return Err(err).with_context(|| format!("failed to read {path}"));
+        }
+        _ => return Ok(BTreeSet::new()),
+    };
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.trim()
+                .parse()
+                .with_context(|| format!("{path}: '{line}' is not a year"))
+        })
+        .collect()
+}
+
+/// with --pipeline the stage list decides what runs, so a stage flag left out
+/// of it would be silently ignored
+fn check_stage_flags(cli: &Cli) -> Result<()> {
+    let right_rules = match &cli.command {
+        Some(Commands::Combine(args)) => args.right_pipeline.contains(&Stage::Rules),
+        _ => false,
+    };
+    let flags = [
+        (Stage::Sanitize, "--sanitize", cli.sanitize),
+        (Stage::Phrase, "--phrases", cli.phrases),
+        (Stage::Case, "--case", cli.case),
+        (Stage::Leet, "--leet", cli.leet),
+        (Stage::Rules, "--rules", cli.rules.is_some() && !right_rules),
+    ];
+    for (stage, flag, given) in flags {
+        if given && !cli.pipeline.contains(&stage) {
+            bail!(
+                "{flag} is given but --pipeline has no {} stage",
+                format!("{stage:?}").to_lowercase()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// mask candidates only go through the candidate filters, so a stage flag
+/// given with the mask command would be silently ignored
+fn check_mask_flags(cli: &Cli) -> Result<()> {
+    if !matches!(cli.command, Some(Commands::Mask(_))) {
+        return Ok(());
+    }
+    let flags = [
+        ("--sanitize", cli.sanitize),
+        ("--phrases", cli.phrases),
+        ("--case", cli.case),
+        ("--leet", cli.leet),
+        ("--rules", cli.rules.is_some()),
+        ("--pipeline", !cli.pipeline.is_empty()),
+    ];
+    for (flag, given) in flags {
+        if given {
+            bail!("{flag} does not apply to the mask command, which only filters its candidates");
+        }
+    }
+    Ok(())
+}
+
+/// --sanitize-min-len and --sanitize-max-len, or bounds that fit the length
+/// subcommand: a base word longer than `length --max` can't make a candidate,
+/// unless a stage before chars shortens it first
+fn sanitize_bounds(
+    cli: &Cli,
+    after: &[Stage],
+    command: &PipelineCommand,
+    leet_table: &LeetTable,
+) -> (usize, usize) {
+    let length = match (&cli.command, command) {
+        (Some(Commands::Length(opts)), PipelineCommand::Length { .. })
+            if after.contains(&Stage::Chars) =>
+        {
+            Some(opts)
+        }
+        _ => None,
+    };
+    let shrinks = after
+        .iter()
+        .take_while(|stage| **stage != Stage::Chars)
+        .any(|stage| match stage {
+            Stage::Sanitize | Stage::Phrase | Stage::Rules => true,
+            Stage::Leet => !leet_table.never_shortens(),
+            Stage::Case | Stage::Chars => false,
+        });
+    let min = cli
+        .sanitize_min_len
+        .unwrap_or_else(|| length.map_or(2, |opts| opts.min.clamp(1, 2)));
+    let max = cli.sanitize_max_len.unwrap_or(match length {
+        Some(opts) if shrinks => opts.max.max(64),
+        Some(opts) => opts.max,
+        None => 64,
+    });
+    (min, max)
+}
+
 #[derive(Clone)]
 pub enum PipelineCommand {
     None,
+    /// with a spill config, the bfs frontier goes to temp files
     Length {
-        min: usize,
-        max: usize,
-        append: bool,
-        prepend: bool,
-        insert: bool,
-        dedup: bool,
+        config: LengthConfig,
+        spill: Option<SpillConfig>,
     },
-    Count {
-        append: usize,
-        prepend: usize,
-        insert: usize,
+    Count(CountConfig),
+    Hybrid {
+        prepend: Mask,
+        append: Mask,
     },
+    /// every word followed by a separator and a word of the right-hand list
+    Combine(Arc<Combination>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::estimate::estimate_line;
+    use super::super::stages::run_chain;
+    use super::*;
+    use clap::Parser;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(["cemplox", "--file", "words.txt"].iter().chain(args)).unwrap()
+    }
+
+    fn variants(args: &[&str], word: &str) -> Result<Vec<String>> {
+        let options = PipelineOptions::from_cli(&cli(args), &Arc::default())?;
+        let mut out = Vec::new();
+        run_chain(&options.transforms, word.to_string(), &mut |variant| {
+            out.push(variant);
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    #[test]
+    fn default_stages_follow_the_flags() {
+        assert_eq!(default_stages(&cli(&[])), [Stage::Chars]);
+        assert_eq!(
+            default_stages(&cli(&["-l", "-s", "-c", "--phrases", "-r", "x.rule"])),
+            [
+                Stage::Sanitize,
+                Stage::Phrase,
+                Stage::Case,
+                Stage::Leet,
+                Stage::Rules,
+                Stage::Chars
+            ]
+        );
+    }
+
+    fn bounds(args: &[&str]) -> (usize, usize) {
+        let cli = cli(args);
+        let command = match &cli.command {
+            Some(Commands::Length(_)) => PipelineCommand::Length {
+                config: LengthConfig::new("a"),
+                spill: None,
+            },
+            _ => PipelineCommand::Count(CountConfig::new("a")),
+        };
+        let stages = default_stages(&cli);
+        let table = LeetTable::preset(cli.leet_preset);
+        sanitize_bounds(&cli, &stages[1..], &command, &table)
+    }
+
+    #[test]
+    fn fits_sanitize_bounds_to_the_length_subcommand() {
+        assert_eq!(bounds(&["-s", "length", "-m", "1", "-M", "10"]), (1, 10));
+        assert_eq!(bounds(&["-s", "length", "-m", "5", "-M", "12"]), (2, 12));
+        assert_eq!(bounds(&["-s", "-l", "length", "-M", "10"]), (2, 10));
+        assert_eq!(
+            bounds(&["-s", "-l", "--leet-preset", "full", "length", "-M", "10"]),
+            (2, 64)
+        );
+        assert_eq!(bounds(&["-s", "--phrases", "length", "-M", "10"]), (2, 64));
+        assert_eq!(bounds(&["-s", "count", "--append", "2"]), (2, 64));
+        assert_eq!(
+            bounds(&[
+                "-s",
+                "--sanitize-min-len",
+                "3",
+                "--sanitize-max-len",
+                "20",
+                "length",
+                "-M",
+                "10"
+            ]),
+            (3, 20)
+        );
+    }
+
+    #[test]
+    fn keeps_harvested_years_on_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("years.txt");
+        let path = path.to_str().unwrap();
+        assert!(harvested_years(path, true).unwrap().is_empty());
+        fs::write(path, "1997\n1984\n").unwrap();
+        assert_eq!(
+            harvested_years(path, true).unwrap(),
+            BTreeSet::from([1984, 1997])
+        );
+        assert!(harvested_years(path, false).unwrap().is_empty());
+        fs::write(path, "1997\nfilm\n").unwrap();
+        assert!(harvested_years(path, true).is_err());
+    }
+
+    #[test]
+    fn runs_stages_in_pipeline_order() {
+        let count = ["-C", "a", "count", "--append", "1"];
+        let leet_first = [&["--pipeline", "leet,chars"][..], &count].concat();
+        assert_eq!(variants(&leet_first, "b").unwrap(), ["b", "ba", "8", "8a"]);
+        let chars_first = [&["--pipeline", "chars,leet"][..], &count].concat();
+        assert_eq!(
+            variants(&chars_first, "b").unwrap(),
+            ["b", "8", "ba", "b4", "b@", "8a", "84", "8@"]
+        );
+    }
+
+    #[test]
+    fn rejects_stage_flags_missing_from_the_pipeline() {
+        for flag in ["-s", "-c", "-l", "--phrases"] {
+            assert!(variants(&[flag, "--pipeline", "chars", "count"], "b").is_err());
+        }
+        assert!(variants(&["-l", "--pipeline", "leet,chars", "count"], "b").is_ok());
+        // the chars stage has one name, whatever the subcommand
+        for stage in ["length", "count", "hybrid", "combine"] {
+            let args = ["--pipeline", stage, "count", "--append", "1"];
+            assert!(
+                Cli::try_parse_from(["cemplox", "--file", "words.txt"].iter().chain(&args))
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_stage_flags_with_the_mask_command() {
+        for flags in [&["-l"][..], &["-c", "-s"], &["--pipeline", "leet,chars"]] {
+            let args: Vec<&str> = flags.iter().copied().chain(["mask", "a?d"]).collect();
+            assert!(PipelineOptions::from_cli(&cli(&args), &Arc::default()).is_err());
+        }
+        let args = ["--min-len", "2", "mask", "a?d"];
+        assert!(PipelineOptions::from_cli(&cli(&args), &Arc::default()).is_ok());
+    }
+
+    #[test]
+    fn estimates_stages_after_chars_from_the_real_variants() {
+        let args = [
+            "--pipeline",
+            "chars,leet",
+            "-l",
+            "-C",
+            "aeox",
+            "count",
+            "--append",
+            "2",
+        ];
+        for word in ["ox", "pass", "xa1"] {
+            let options = PipelineOptions::from_cli(&cli(&args), &Arc::default()).unwrap();
+            let generated = variants(&args, word).unwrap();
+            let estimate = estimate_line(word, &options);
+            assert_eq!(estimate.candidates, generated.len() as u128, "{word}");
+            let bytes: usize = generated.iter().map(|variant| variant.len() + 1).sum();
+            assert_eq!(estimate.bytes, bytes as u128, "{word}");
+        }
+    }
 }
@@ -0,0 +1,151 @@
+use anyhow::{Error, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use super::worker::WorkerState;
+
+/// limits how far workers may run ahead of the writer in ordered mode, which
+/// bounds the number of chunks and bytes the writer has to hold back
+pub struct OrderWindow {
+    state: Mutex<WindowState>,
+    changed: Condvar,
+    size: usize,
+    max_bytes: usize,
+}
+
+struct WindowState {
+    written: usize,
+    /// bytes sent to the writer and not yet written out
+    pending_bytes: usize,
+    /// the writer has stopped, so nothing will advance the window again
+    closed: bool,
+}
+
+/// the writer is gone, so there is no point in producing more output
+#[derive(Debug)]
+pub struct Closed;
+
+impl OrderWindow {
+    pub fn new(size: usize, max_bytes: usize) -> Self {
+        Self {
+            state: Mutex::new(WindowState {
+                written: 0,
+                pending_bytes: 0,
+                closed: false,
+            }),
+            changed: Condvar::new(),
+            size: size.max(1),
+            max_bytes,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WindowState> {
+        self.state.lock().expect("order window poisoned")
+    }
+
+    fn wait_while(
+        &self,
+        blocked: impl Fn(&WindowState) -> bool,
+    ) -> Result<MutexGuard<'_, WindowState>, Closed> {
+        let mut state = self.lock();
+        while !state.closed && blocked(&state) {
+            state = self.changed.wait(state).expect("order window poisoned");
+        }
+        if state.closed {
+            return Err(Closed);
+        }
+        Ok(state)
+    }
+
+    fn wait_for(&self, seq: usize) -> Result<(), Closed> {
+        self.wait_while(|state| seq >= state.written + self.size)
+            .map(|_| ())
+    }
+
+    /// wait until `bytes` of chunk `seq` fit next to what the writer already
+    /// holds. the chunk the writer is waiting on never waits, so the window
+    /// always drains
+    pub fn reserve(&self, seq: usize, bytes: usize) -> Result<(), Closed> {
+        let mut state = self.wait_while(|state| {
+            seq != state.written
+                && state.pending_bytes > 0
+                && state.pending_bytes + bytes > self.max_bytes
+        })?;
+        state.pending_bytes += bytes;
+        Ok(())
+    }
+
+    /// the writer wrote out `bytes` it was sent
+    pub fn release(&self, bytes: usize) {
+        let mut state = self.lock();
+        state.pending_bytes = state.pending_bytes.saturating_sub(bytes);
+        self.changed.notify_all();
+    }
+
+    /// every chunk before `written` is on its way to the output
+    pub fn advance(&self, written: usize) {
+        self.lock().written = written;
+        self.changed.notify_all();
+    }
+
+    /// wake every waiting worker for good, once the writer stops
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// run `work` on every job `next` hands out, on `threads` pool workers. jobs
+/// come with their sequence number and are claimed in sequence order, so the
+/// writer never waits long on a straggler. workers stop early once the writer
+/// is gone, and all of them stop at the first error `work` returns
+pub fn run_jobs<J, N, I, W>(
+    threads: usize,
+    window: Option<&OrderWindow>,
+    capacity: usize,
+    next: N,
+    init: I,
+    work: W,
+) -> Result<()>
+where
+    N: Fn() -> Option<(usize, J)> + Sync,
+    I: Fn() -> WorkerState + Sync,
+    W: Fn(J, &mut WorkerState) -> Result<()> + Sync,
+{
+    let failed = AtomicBool::new(false);
+    let first_error: Mutex<Option<Error>> = Mutex::new(None);
+    rayon::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|_| {
+                let mut state = init();
+                while !state.stopped() && !failed.load(Ordering::Relaxed) {
+                    let Some((seq, job)) = next() else {
+                        break;
+                    };
+                    if window.is_some_and(|window| window.wait_for(seq).is_err()) {
+                        break;
+                    }
+                    state.begin_chunk(seq);
+                    if let Err(err) = work(job, &mut state) {
+                        failed.store(true, Ordering::Relaxed);
+                        first_error
+                            .lock()
+                            .expect("worker error poisoned")
+                            .get_or_insert(err);
+                        // the failed chunk never completes, so nothing that
+                        // waits on the window would ever wake up
+                        if let Some(window) = window {
+                            window.close();
+                        }
+                        break;
+                    }
+                    state.finish_chunk(capacity);
+                }
+            });
+        }
+    });
+    match first_error.into_inner().expect("worker error poisoned") {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
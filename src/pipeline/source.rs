@@ -0,0 +1,501 @@
+use anyhow::{bail, Context, Result};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use log::warn;
+use memmap2::Mmap;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use super::chunks::{self, Pending};
+use super::compress::{self, Format};
+use super::estimate::{estimate_mask, estimate_raw_line, Estimate};
+use super::metrics::Metrics;
+use super::options::PipelineOptions;
+use super::stages::{emit, process_line};
+use super::worker::WorkerState;
+use crate::cli::MaskArgs;
+use cemplox::character_combinations::Mask;
+
+/// what the workers split into jobs: line ranges of the input files, batches
+/// of lines read from a stream, or slices of a mask keyspace
+pub enum Source {
+    /// chunks are cut from the files as they are handed out
+    Files {
+        files: Vec<MappedFile>,
+        /// index of the first chunk of each file, and of the chunk after the last
+        offsets: Vec<usize>,
+        chunk_size: usize,
+    },
+    /// stdin, pipes, compressed files and other inputs that cannot be mapped. they are read
+    /// front to back by one thread, so the number of jobs is not known up front
+    Stream {
+        inputs: Vec<String>,
+        batch_size: usize,
+    },
+    Masks {
+        masks: Vec<Mask>,
+        /// index of the first job of each mask, and of the job after the last
+        offsets: Vec<usize>,
+        job_size: u128,
+    },
+}
+
+pub struct MappedFile {
+    path: String,
+    mmap: Mmap,
+}
+
+/// one unit of work: a numbered chunk of a file or mask source, or a batch of
+/// streamed lines
+pub enum Job {
+    Chunk(usize),
+    Batch(Vec<u8>),
+}
+
+/// hands out jobs with their sequence numbers, in sequence order
+pub enum Jobs {
+    Pending { chunks: Pending, next: AtomicUsize },
+    Stream(Receiver<(usize, Vec<u8>)>),
+}
+
+impl Jobs {
+    pub fn next(&self) -> Option<(usize, Job)> {
+        match self {
+            Jobs::Pending { chunks, next } => {
+                let seq = next.fetch_add(1, Ordering::Relaxed);
+                chunks.chunk(seq).map(|idx| (seq, Job::Chunk(idx)))
+            }
+            Jobs::Stream(receiver) => receiver
+                .recv()
+                .ok()
+                .map(|(seq, data)| (seq, Job::Batch(data))),
+        }
+    }
+}
+
+impl Source {
+    /// `-` is stdin and directories are searched recursively. regular files
+    /// are mapped; if any input is compressed or not a regular file, every
+    /// input is streamed
+    pub fn lines(paths: &[String], chunk_size: usize) -> Result<Self> {
+        let mut inputs = Vec::new();
+        let mut visited = HashSet::new();
+        for path in paths {
+            collect_inputs(Path::new(path), &mut inputs, &mut visited)?;
+        }
+        if inputs.is_empty() {
+            bail!("no input files found in {}", paths.join(", "));
+        }
+        if inputs.iter().any(|(_, mappable)| !mappable) {
+            return Ok(Source::Stream {
+                inputs: inputs.into_iter().map(|(path, _)| path).collect(),
+                batch_size: chunk_size,
+            });
+        }
+        let mut files = Vec::with_capacity(inputs.len());
+        let mut offsets = vec![0];
+        for (path, _) in inputs {
+            let input_file =
+                File::open(&path).with_context(|| format!("failed to open input file {path}"))?;
+            let mmap = unsafe { Mmap::map(&input_file)? };
+            let total = offsets[offsets.len() - 1] + chunks::count(mmap.len(), chunk_size);
+            offsets.push(total);
+            files.push(MappedFile { path, mmap });
+        }
+        Ok(Source::Files {
+            files,
+            offsets,
+            chunk_size,
+        })
+    }
+
+    /// the lines of file chunk `idx`
+    fn file_chunk(&self, idx: usize) -> &[u8] {
+        let Source::Files {
+            files,
+            offsets,
+            chunk_size,
+        } = self
+        else {
+            unreachable!("only file sources have file chunks");
+        };
+        let file = offsets.partition_point(|offset| *offset <= idx) - 1;
+        let mmap = &files[file].mmap;
+        &mmap[chunks::chunk(mmap, *chunk_size, idx - offsets[file])]
+    }
+
+    /// one job per `job_size` candidates, never spanning two masks. jobs are
+    /// only counted here and sliced out of the keyspace as they are handed out
+    pub fn masks(args: &MaskArgs, job_size: u128) -> Result<Self> {
+        let mask = Mask::parse(&args.mask, &args.charsets.to_vec())?;
+        let masks = if args.increment {
+            let max = args.increment_max.unwrap_or(mask.len());
+            let masks = mask.prefixes(args.increment_min, max);
+            if masks.is_empty() {
+                bail!(
+                    "--increment-min {} selects no mask positions",
+                    args.increment_min
+                );
+            }
+            masks
+        } else {
+            vec![mask]
+        };
+        let mut offsets = vec![0];
+        let mut total = 0usize;
+        for mask in &masks {
+            let jobs = usize::try_from(mask.keyspace().div_ceil(job_size))
+                .ok()
+                .and_then(|jobs| total.checked_add(jobs));
+            let Some(jobs) = jobs else {
+                bail!(
+                    "mask {} has too many candidates to split into jobs",
+                    mask.text()
+                );
+            };
+            total = jobs;
+            offsets.push(total);
+        }
+        Ok(Source::Masks {
+            masks,
+            offsets,
+            job_size,
+        })
+    }
+
+    /// the mask and candidate range behind mask job `idx`
+    fn mask_job(&self, idx: usize) -> (&Mask, u128, u128) {
+        let Source::Masks {
+            masks,
+            offsets,
+            job_size,
+        } = self
+        else {
+            unreachable!("only mask sources have mask jobs");
+        };
+        let mask = offsets.partition_point(|offset| *offset <= idx) - 1;
+        let start = (idx - offsets[mask]) as u128 * job_size;
+        let end = start.saturating_add(*job_size).min(masks[mask].keyspace());
+        (&masks[mask], start, end)
+    }
+
+    /// number of chunks, 0 for streams
+    pub fn len(&self) -> usize {
+        match self {
+            Source::Files { offsets, .. } => offsets.last().copied().unwrap_or(0),
+            Source::Stream { .. } => 0,
+            Source::Masks { offsets, .. } => offsets.last().copied().unwrap_or(0),
+        }
+    }
+
+    /// name, size and chunk size a checkpoint records to recognise this input
+    pub fn identity(&self) -> Result<(String, u64, usize)> {
+        match self {
+            Source::Files {
+                files, chunk_size, ..
+            } => {
+                let names: Vec<&str> = files.iter().map(|file| file.path.as_str()).collect();
+                let size = files.iter().map(|file| file.mmap.len() as u64).sum();
+                Ok((names.join(", "), size, *chunk_size))
+            }
+            Source::Stream { .. } => {
+                bail!("--checkpoint needs uncompressed regular input files: stdin, pipes and compressed inputs cannot be resumed")
+            }
+            Source::Masks {
+                masks, job_size, ..
+            } => {
+                let names: Vec<String> = masks.iter().map(Mask::text).collect();
+                let keyspace = masks
+                    .iter()
+                    .fold(0u128, |total, mask| total.saturating_add(mask.keyspace()));
+                let size = u64::try_from(keyspace).unwrap_or(u64::MAX);
+                let job_size = usize::try_from(*job_size).unwrap_or(usize::MAX);
+                Ok((format!("mask {}", names.join(", ")), size, job_size))
+            }
+        }
+    }
+
+    /// hand out the `pending` chunks, or start the thread reading a stream.
+    /// the thread's result is only known once every batch has been taken
+    pub fn jobs(
+        &self,
+        pending: Pending,
+        depth: usize,
+    ) -> (Jobs, Option<thread::JoinHandle<Result<()>>>) {
+        match self {
+            Source::Stream { inputs, batch_size } => {
+                let (sender, receiver) = bounded(depth);
+                let inputs = inputs.clone();
+                let batch_size = *batch_size;
+                let reader = thread::spawn(move || read_batches(&inputs, batch_size, sender));
+                (Jobs::Stream(receiver), Some(reader))
+            }
+            _ => (
+                Jobs::Pending {
+                    chunks: pending,
+                    next: AtomicUsize::new(0),
+                },
+                None,
+            ),
+        }
+    }
+
+    /// stops between lines once the writer is gone
+    pub fn process(
+        &self,
+        job: Job,
+        options: &Arc<PipelineOptions>,
+        metrics: &Arc<Metrics>,
+        state: &mut WorkerState,
+        capacity: usize,
+    ) -> Result<()> {
+        match (self, job) {
+            (_, Job::Batch(data)) => {
+                for line in chunks::lines(&data) {
+                    if state.stopped() {
+                        break;
+                    }
+                    process_line(line, options, metrics, state, capacity)?;
+                }
+            }
+            (Source::Files { .. }, Job::Chunk(idx)) => {
+                for line in chunks::lines(self.file_chunk(idx)) {
+                    if state.stopped() {
+                        break;
+                    }
+                    process_line(line, options, metrics, state, capacity)?;
+                }
+            }
+            (Source::Masks { .. }, Job::Chunk(idx)) => {
+                let (mask, start, end) = self.mask_job(idx);
+                for candidate in mask.candidates(start, end) {
+                    if state.stopped() {
+                        break;
+                    }
+                    emit(&candidate, options, metrics, state, capacity);
+                }
+            }
+            (Source::Stream { .. }, Job::Chunk(_)) => unreachable!("streams only hand out batches"),
+        }
+        Ok(())
+    }
+
+    /// mask sources are built with one job per mask for dry runs
+    pub fn estimate(
+        &self,
+        job: Job,
+        options: &Arc<PipelineOptions>,
+        metrics: &Arc<Metrics>,
+        state: &mut WorkerState,
+        capacity: usize,
+    ) -> Estimate {
+        let lines = |data: &[u8], state: &mut WorkerState| {
+            chunks::lines(data)
+                .map(|line| estimate_raw_line(line, options, metrics, state, capacity))
+                .fold(Estimate::default(), Estimate::combine)
+        };
+        match (self, job) {
+            (_, Job::Batch(data)) => lines(&data, state),
+            (Source::Files { .. }, Job::Chunk(idx)) => lines(self.file_chunk(idx), state),
+            (Source::Masks { .. }, Job::Chunk(idx)) => {
+                let (mask, _, _) = self.mask_job(idx);
+                estimate_mask(mask, state, capacity)
+            }
+            (Source::Stream { .. }, Job::Chunk(_)) => unreachable!("streams only hand out batches"),
+        }
+    }
+}
+
+/// every input below `path` with whether it can be mapped: an uncompressed
+/// regular file. directories are walked in name order, each only once, so a
+/// symlink back up the tree is skipped instead of walked forever
+fn collect_inputs(
+    path: &Path,
+    inputs: &mut Vec<(String, bool)>,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    if path == Path::new("-") {
+        inputs.push(("-".to_string(), false));
+        return Ok(());
+    }
+    let metadata = fs::metadata(path)
+        .with_context(|| format!("failed to open input file {}", path.display()))?;
+    if !metadata.is_dir() {
+        let name = path.display().to_string();
+        let mappable = metadata.is_file() && !is_compressed(path, &name)?;
+        inputs.push((name, mappable));
+        return Ok(());
+    }
+    let canonical = fs::canonicalize(path)
+        .with_context(|| format!("failed to read directory {}", path.display()))?;
+    if !visited.insert(canonical) {
+        warn!(
+            "skipping {}, its directory was already read",
+            path.display()
+        );
+        return Ok(());
+    }
+    let mut entries = fs::read_dir(path)
+        .with_context(|| format!("failed to read directory {}", path.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()
+        .with_context(|| format!("failed to read directory {}", path.display()))?;
+    entries.sort();
+    for entry in entries {
+        collect_inputs(&entry, inputs, visited)?;
+    }
+    Ok(())
+}
+
+fn is_compressed(path: &Path, name: &str) -> Result<bool> {
+    let mut head = Vec::with_capacity(compress::MAGIC_LEN);
+    File::open(path)
+        .and_then(|file| file.take(compress::MAGIC_LEN as u64).read_to_end(&mut head))
+        .with_context(|| format!("failed to open input file {name}"))?;
+    Ok(Format::detect(&head, name)?.is_some())
+}
+
+/// a file or `-` for stdin, decoded if it is compressed
+pub fn open_input(name: &str) -> Result<Box<dyn BufRead>> {
+    let input: Box<dyn BufRead> = if name == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        let file = File::open(name).with_context(|| format!("failed to open input file {name}"))?;
+        Box::new(BufReader::new(file))
+    };
+    compress::decode(input, name).with_context(|| format!("failed to read {name}"))
+}
+
+/// read the inputs one after the other and send their lines on in batches of
+/// about `batch_size` bytes. stops early once the workers are gone
+fn read_batches(
+    inputs: &[String],
+    batch_size: usize,
+    sender: Sender<(usize, Vec<u8>)>,
+) -> Result<()> {
+    let mut seq = 0;
+    let mut batch = Vec::with_capacity(batch_size);
+    for name in inputs {
+        let mut reader = open_input(name)?;
+        loop {
+            let read = reader
+                .read_until(b'\n', &mut batch)
+                .with_context(|| format!("failed to read {name}"))?;
+            if read == 0 {
+                break;
+            }
+            // the last line of an input may lack its line break
+            if batch.last() != Some(&b'\n') {
+                batch.push(b'\n');
+            }
+            if batch.len() >= batch_size {
+                let full = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+                if sender.send((seq, full)).is_err() {
+                    return Ok(());
+                }
+                seq += 1;
+            }
+        }
+    }
+    if !batch.is_empty() {
+        let _ = sender.send((seq, batch));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Commands};
+    use clap::Parser;
+
+    fn paths(source: &Source) -> Vec<String> {
+        match source {
+            Source::Files { files, .. } => files.iter().map(|file| file.path.clone()).collect(),
+            Source::Stream { inputs, .. } => inputs.clone(),
+            Source::Masks { .. } => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn walks_directories_in_name_order() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("b")).unwrap();
+        for name in ["c.txt", "a.txt", "b/d.txt"] {
+            fs::write(dir.path().join(name), "word\n").unwrap();
+        }
+        let root = dir.path().to_str().unwrap().to_string();
+        let source = Source::lines(std::slice::from_ref(&root), 4096).unwrap();
+        assert!(matches!(source, Source::Files { .. }));
+        let expected: Vec<String> = ["a.txt", "b/d.txt", "c.txt"]
+            .iter()
+            .map(|name| dir.path().join(name).display().to_string())
+            .collect();
+        assert_eq!(paths(&source), expected);
+        assert!(Source::lines(&[format!("{root}/missing")], 4096).is_err());
+    }
+
+    #[test]
+    fn streams_every_input_when_one_is_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "word\n").unwrap();
+        let file = file.display().to_string();
+        let source = Source::lines(&[file.clone(), "-".to_string()], 4096).unwrap();
+        assert!(matches!(source, Source::Stream { .. }));
+        assert_eq!(paths(&source), [file, "-".to_string()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_symlinks_back_up_the_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/a.txt"), "word\n").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub/loop")).unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        let source = Source::lines(&[root], 4096).unwrap();
+        assert_eq!(
+            paths(&source),
+            [dir.path().join("sub/a.txt").display().to_string()]
+        );
+    }
+
+    fn mask_source(args: &[&str], job_size: u128) -> Result<Source> {
+        let cli = Cli::try_parse_from(["cemplox", "mask"].iter().chain(args)).unwrap();
+        let Some(Commands::Mask(args)) = cli.command else {
+            unreachable!("parsed the mask command");
+        };
+        Source::masks(&args, job_size)
+    }
+
+    #[test]
+    fn slices_mask_jobs_out_of_the_keyspace() {
+        let source = mask_source(&["?d?d", "--increment"], 4).unwrap();
+        assert_eq!(source.len(), 3 + 25);
+        let jobs: Vec<(String, u128, u128)> = [0, 2, 3, 27]
+            .into_iter()
+            .map(|idx| {
+                let (mask, start, end) = source.mask_job(idx);
+                (mask.text(), start, end)
+            })
+            .collect();
+        assert_eq!(
+            jobs,
+            [
+                ("?d".to_string(), 0, 4),
+                ("?d".to_string(), 8, 10),
+                ("?d?d".to_string(), 0, 4),
+                ("?d?d".to_string(), 96, 100),
+            ]
+        );
+        // jobs are only counted, so a huge keyspace costs nothing up front
+        let huge = mask_source(&["?a?a?a?a?a?a?a?a"], 1 << 16).unwrap();
+        assert_eq!(huge.len(), 101_229_924_208);
+        assert!(mask_source(&[&"?a".repeat(40)], 1 << 16).is_err());
+    }
+}
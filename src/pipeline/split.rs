@@ -0,0 +1,305 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::compress::{Compressor, Encoder};
+use cemplox::character_combinations::LengthUnit;
+
+/// when to start a new output shard
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SplitConfig {
+    /// uncompressed bytes per shard
+    pub bytes: Option<u64>,
+    pub lines: Option<u64>,
+    /// one set of shards per candidate length, measured in `unit`s
+    pub by_length: bool,
+    pub unit: LengthUnit,
+}
+
+impl SplitConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.bytes.is_some() || self.lines.is_some() || self.by_length
+    }
+
+    fn rotates(&self) -> bool {
+        self.bytes.is_some() || self.lines.is_some()
+    }
+
+    /// `out.txt` becomes `out.len{len}.{n}.txt`, keeping only the placeholders
+    /// the config needs. a path that has placeholders of its own is used as is
+    pub fn template(&self, path: &str) -> Result<String> {
+        if path.contains("{n}") || path.contains("{len}") {
+            if self.rotates() && !path.contains("{n}") {
+                bail!("--out-file {path} needs a {{n}} placeholder to split by size");
+            }
+            if self.by_length && !path.contains("{len}") {
+                bail!("--out-file {path} needs a {{len}} placeholder to split by length");
+            }
+            return Ok(path.to_string());
+        }
+        let name_start = path.len() - Path::new(path).file_name().map_or(0, |name| name.len());
+        let (stem, ext) = match path[name_start..].rfind('.') {
+            Some(dot) if dot > 0 => path.split_at(name_start + dot),
+            _ => (path, ""),
+        };
+        let mut template = stem.to_string();
+        if self.by_length {
+            template.push_str(".len{len}");
+        }
+        if self.rotates() {
+            template.push_str(".{n}");
+        }
+        template.push_str(ext);
+        Ok(template)
+    }
+}
+
+/// `10G`, `512M`, `64k` or plain bytes. units are powers of 1024
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let digits = text.trim_end_matches(|ch: char| ch.is_ascii_alphabetic());
+    let scale: u64 = match text[digits.len()..].to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        unit => return Err(format!("unknown size unit '{unit}', expected K, M, G or T")),
+    };
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("'{text}' is not a size like 10G"))?;
+    match value.checked_mul(scale) {
+        Some(0) => Err("shards must hold at least one byte".to_string()),
+        Some(size) => Ok(size),
+        None => Err(format!("'{text}' is too large")),
+    }
+}
+
+/// routes every line to its shard, opening and rotating shard files as it
+/// goes. expects whole lines, but holds on to an unfinished one until the
+/// rest of it arrives
+pub struct ShardWriter {
+    template: String,
+    config: SplitConfig,
+    /// shared by every shard, each compressed on its own
+    compressor: Option<Arc<Compressor>>,
+    shards: HashMap<Option<usize>, Shard>,
+    partial: Vec<u8>,
+}
+
+struct Shard {
+    file: Encoder<BufWriter<File>>,
+    index: usize,
+    bytes: u64,
+    lines: u64,
+}
+
+impl ShardWriter {
+    pub fn new(template: String, config: SplitConfig, compressor: Option<Arc<Compressor>>) -> Self {
+        Self {
+            template,
+            config,
+            compressor,
+            shards: HashMap::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let key = self.config.by_length.then(|| {
+            let text = line.strip_suffix(b"\n").unwrap_or(line);
+            std::str::from_utf8(text).map_or(text.len(), |text| self.config.unit.measure(text))
+        });
+        let full = self.shards.get(&key).is_some_and(|shard| {
+            shard.lines > 0
+                && (self
+                    .config
+                    .bytes
+                    .is_some_and(|limit| shard.bytes + line.len() as u64 > limit)
+                    || self.config.lines.is_some_and(|limit| shard.lines >= limit))
+        });
+        let index = match self.shards.get_mut(&key) {
+            Some(shard) if full => {
+                shard.file.flush()?;
+                Some(shard.index + 1)
+            }
+            Some(_) => None,
+            None => Some(0),
+        };
+        if let Some(index) = index {
+            let path = self.shard_path(key, index);
+            let file = File::create(&path).map_err(|err| {
+                io::Error::new(err.kind(), format!("failed to create shard {path}: {err}"))
+            })?;
+            self.shards.insert(
+                key,
+                Shard {
+                    file: Encoder::new(BufWriter::new(file), self.compressor.clone()),
+                    index,
+                    bytes: 0,
+                    lines: 0,
+                },
+            );
+        }
+        let shard = self.shards.get_mut(&key).expect("shard was just opened");
+        shard.bytes += line.len() as u64;
+        shard.lines += 1;
+        shard.file.write_all(line)
+    }
+
+    fn shard_path(&self, len: Option<usize>, index: usize) -> String {
+        let path = self.template.replace("{n}", &format!("{index:04}"));
+        match len {
+            Some(len) => path.replace("{len}", &format!("{len:02}")),
+            None => path,
+        }
+    }
+}
+
+impl Write for ShardWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while let Some(end) = rest.iter().position(|b| *b == b'\n') {
+            let (line, tail) = rest.split_at(end + 1);
+            if self.partial.is_empty() {
+                self.write_line(line)?;
+            } else {
+                self.partial.extend_from_slice(line);
+                let line = std::mem::take(&mut self.partial);
+                self.write_line(&line)?;
+            }
+            rest = tail;
+        }
+        self.partial.extend_from_slice(rest);
+        Ok(buf.len())
+    }
+
+    /// also ends the current compressed frames, so everything written so far
+    /// can be read back
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.write_line(&line)?;
+        }
+        for shard in self.shards.values_mut() {
+            shard.file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parses_sizes_in_powers_of_1024() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64k"), Ok(64 << 10));
+        assert_eq!(parse_size("10G"), Ok(10 << 30));
+        assert_eq!(parse_size("2MiB"), Ok(2 << 20));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("99999999T").is_err());
+    }
+
+    #[test]
+    fn builds_templates_from_the_output_path() {
+        let by_length = SplitConfig {
+            by_length: true,
+            ..SplitConfig::default()
+        };
+        let both = SplitConfig {
+            lines: Some(10),
+            ..by_length
+        };
+        assert_eq!(
+            both.template("out/words.txt").unwrap(),
+            "out/words.len{len}.{n}.txt"
+        );
+        assert_eq!(
+            by_length.template("words.txt.gz").unwrap(),
+            "words.txt.len{len}.gz"
+        );
+        assert_eq!(
+            both.template("a.b/words").unwrap(),
+            "a.b/words.len{len}.{n}"
+        );
+        assert_eq!(both.template(".words").unwrap(), ".words.len{len}.{n}");
+        assert_eq!(both.template("{len}/{n}.txt").unwrap(), "{len}/{n}.txt");
+        assert!(both.template("{len}.txt").is_err());
+        assert!(by_length.template("{n}.txt").is_err());
+    }
+
+    fn write_shards(config: SplitConfig, data: &[u8]) -> Vec<(String, String)> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.txt");
+        let template = config.template(path.to_str().unwrap()).unwrap();
+        let mut writer = ShardWriter::new(template, config, None);
+        // split mid-line to check partial lines are held back
+        let (head, tail) = data.split_at(data.len() / 2);
+        writer.write_all(head).unwrap();
+        writer.write_all(tail).unwrap();
+        writer.flush().unwrap();
+        let mut shards: Vec<(String, String)> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        shards.sort();
+        shards
+    }
+
+    #[test]
+    fn rotates_shards_by_lines_and_bytes() {
+        let data = b"aa\nbbb\nc\ndddd\ne\n";
+        let by_lines = SplitConfig {
+            lines: Some(2),
+            ..SplitConfig::default()
+        };
+        assert_eq!(
+            write_shards(by_lines, data),
+            [
+                ("out.0000.txt".to_string(), "aa\nbbb\n".to_string()),
+                ("out.0001.txt".to_string(), "c\ndddd\n".to_string()),
+                ("out.0002.txt".to_string(), "e\n".to_string()),
+            ]
+        );
+        let by_bytes = SplitConfig {
+            bytes: Some(8),
+            ..SplitConfig::default()
+        };
+        assert_eq!(
+            write_shards(by_bytes, data),
+            [
+                ("out.0000.txt".to_string(), "aa\nbbb\n".to_string()),
+                ("out.0001.txt".to_string(), "c\ndddd\n".to_string()),
+                ("out.0002.txt".to_string(), "e\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_by_length_in_the_configured_unit() {
+        let config = SplitConfig {
+            by_length: true,
+            unit: LengthUnit::Chars,
+            ..SplitConfig::default()
+        };
+        assert_eq!(
+            write_shards(config, "ab\néé\nabc\nc\u{327}d\n".as_bytes()),
+            [
+                ("out.len02.txt".to_string(), "ab\néé\n".to_string()),
+                ("out.len03.txt".to_string(), "abc\nc\u{327}d\n".to_string()),
+            ]
+        );
+    }
+}
@@ -1,117 +1,265 @@
-use std::sync::Arc;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
 use super::metrics::Metrics;
 use super::options::{PipelineCommand, PipelineOptions};
 use super::worker::WorkerState;
-use crate::character_combinations::{stream_count, stream_length};
-use crate::{case_combinations, leet_combinations, sanitize};
+use cemplox::case_combinations::{case_modes_keyspace, stream_case_modes, CaseMode};
+use cemplox::character_combinations::{hybrid_keyspace, stream_hybrid, Mask};
+use cemplox::leet_combinations::{leet_keyspace, stream_leet, LeetTable};
+use cemplox::phrase_combinations::{stream_phrases, PhraseStyle};
+use cemplox::rules::{stream_rules, Rule};
+use cemplox::sanitize::{LengthRejection, SanitizeConfig};
 
-pub fn process_chunk(
-    chunk: &[u8],
+/// one link of the transform chain: turns a word into zero or more candidates
+pub trait Transform: Send + Sync {
+    /// stops at the first error, from this stage or from `visit`
+    fn apply(&self, word: String, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()>;
+
+    /// what `apply` would make of `word`, as representative variants paired
+    /// with how many candidates each one stands for
+    fn estimate(&self, word: &str) -> Vec<(String, u128)>;
+}
+
+pub fn process_line(
+    line_bytes: &[u8],
     options: &Arc<PipelineOptions>,
     metrics: &Arc<Metrics>,
     state: &mut WorkerState,
     capacity: usize,
-) {
-    let line_bytes = if matches!(chunk.last(), Some(b'\r')) {
-        &chunk[..chunk.len().saturating_sub(1)]
-    } else {
-        chunk
-    };
+) -> Result<()> {
+    let line_bytes = line_bytes.strip_suffix(b"\r").unwrap_or(line_bytes);
 
     match std::str::from_utf8(line_bytes) {
         Ok(line) => {
             metrics.record_line();
-            for_each_sanitized(line, options, |sanitized| {
-                for_each_case(sanitized, options, |case_variant| {
-                    for_each_leet(case_variant, options, |leet_variant| {
-                        for_each_chars(leet_variant, options, |final_variant| {
-                            state.push_line(&final_variant, capacity);
-                            metrics.record_variant();
-                        });
-                    });
-                });
-            });
+            run_chain(&options.transforms, line.to_owned(), &mut |final_variant| {
+                emit(&final_variant, options, metrics, state, capacity);
+                Ok(())
+            })
+        }
+        Err(_) => {
+            metrics.record_invalid();
+            Ok(())
         }
-        Err(_) => metrics.record_invalid(),
     }
 }
-fn for_each_sanitized<F>(line: &str, options: &Arc<PipelineOptions>, mut visit: F)
-where
-    F: FnMut(String),
-{
-    if options.sanitize {
-        for candidate in sanitize::stream(line) {
-            visit(candidate);
-        }
+
+/// filter the candidate, then hand it to the worker buffer
+pub fn emit(
+    candidate: &str,
+    options: &PipelineOptions,
+    metrics: &Metrics,
+    state: &mut WorkerState,
+    capacity: usize,
+) {
+    if options.filter.as_ref().is_some_and(|filter| !filter.accepts(candidate)) {
+        metrics.record_filtered();
+    } else if state.push_line(candidate, capacity) {
+        metrics.record_variant();
     } else {
-        visit(line.to_owned());
+        metrics.record_duplicate();
     }
 }
-fn for_each_case<F>(word: String, options: &Arc<PipelineOptions>, mut visit: F)
-where
-    F: FnMut(String),
-{
-    if options.case {
-        for variant in case_combinations::stream_cases(&word, options.case_max_changes) {
-            visit(variant);
-        }
-    } else {
-        visit(word);
+
+pub fn run_chain(
+    transforms: &[Box<dyn Transform>],
+    word: String,
+    sink: &mut dyn FnMut(String) -> Result<()>,
+) -> Result<()> {
+    match transforms.split_first() {
+        Some((first, rest)) => first.apply(word, &mut |variant| run_chain(rest, variant, sink)),
+        None => sink(word),
     }
 }
-fn for_each_leet<F>(word: String, options: &Arc<PipelineOptions>, mut visit: F)
-where
-    F: FnMut(String),
-{
-    if options.leet {
-        for variant in leet_combinations::stream_leet(&word, options.leet_max_substitutions) {
-            visit(variant);
+
+pub struct Sanitize {
+    pub config: SanitizeConfig,
+    /// release years seen so far, when harvesting them
+    pub years: Option<Arc<Mutex<BTreeSet<u16>>>>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl Sanitize {
+    /// the candidates within the length bounds. the release year, if any, is
+    /// harvested on the way
+    fn checked(&self, word: &str) -> impl Iterator<Item = String> + '_ {
+        let (year, candidates) = self.config.stream_checked(word);
+        if let (Some(years), Some(year)) = (&self.years, year) {
+            years.lock().expect("year set poisoned").insert(year);
         }
-    } else {
-        visit(word);
-    }
-}
-fn for_each_chars<F>(word: String, options: &Arc<PipelineOptions>, mut visit: F)
-where
-    F: FnMut(String),
-{
-    match &options.command {
-        PipelineCommand::None => visit(word),
-        PipelineCommand::Length {
-            min,
-            max,
-            append,
-            prepend,
-            insert,
-            dedup,
-        } => {
-            for variant in stream_length(
-                &word,
-                &options.chars,
-                *min,
-                *max,
-                *append,
-                *prepend,
-                *insert,
-                *dedup,
-            ) {
-                visit(variant);
+        candidates.filter_map(|candidate| match candidate {
+            Ok(candidate) => Some(candidate),
+            Err(LengthRejection::TooShort) => {
+                self.metrics.record_too_short();
+                None
             }
-        }
-        PipelineCommand::Count {
-            append,
-            prepend,
-            insert,
-        } => {
-            for variant in stream_count(
-                &word,
-                &options.chars,
-                *append,
-                *prepend,
-                *insert,
-            ) {
-                visit(variant);
+            Err(LengthRejection::TooLong) => {
+                self.metrics.record_too_long();
+                None
             }
+        })
+    }
+}
+
+impl Transform for Sanitize {
+    fn apply(&self, word: String, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()> {
+        self.checked(&word).try_for_each(visit)
+    }
+
+    fn estimate(&self, word: &str) -> Vec<(String, u128)> {
+        self.checked(word)
+            .map(|candidate| (candidate, 1))
+            .collect()
+    }
+}
+
+pub struct Phrase {
+    pub styles: Vec<PhraseStyle>,
+}
+
+impl Transform for Phrase {
+    fn apply(&self, word: String, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()> {
+        stream_phrases(&word, &self.styles).try_for_each(visit)
+    }
+
+    fn estimate(&self, word: &str) -> Vec<(String, u128)> {
+        stream_phrases(word, &self.styles)
+            .map(|variant| (variant, 1))
+            .collect()
+    }
+}
+
+pub struct Case {
+    pub modes: Vec<CaseMode>,
+    pub max_changes: Option<usize>,
+}
+
+impl Transform for Case {
+    fn apply(&self, word: String, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()> {
+        stream_case_modes(&word, &self.modes, self.max_changes).try_for_each(visit)
+    }
+
+    /// case mostly swaps chars one for one, so the word stands for all its variants
+    fn estimate(&self, word: &str) -> Vec<(String, u128)> {
+        let count = case_modes_keyspace(word, &self.modes, self.max_changes);
+        vec![(word.to_owned(), count)]
+    }
+}
+
+pub struct Leet {
+    pub table: LeetTable,
+    pub max_substitutions: Option<usize>,
+}
+
+impl Transform for Leet {
+    fn apply(&self, word: String, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()> {
+        stream_leet(&word, &self.table, self.max_substitutions).try_for_each(visit)
+    }
+
+    fn estimate(&self, word: &str) -> Vec<(String, u128)> {
+        leet_keyspace(word, &self.table, self.max_substitutions)
+    }
+}
+
+pub struct Rules {
+    pub rules: Vec<Rule>,
+}
+
+impl Transform for Rules {
+    fn apply(&self, word: String, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()> {
+        stream_rules(&word, &self.rules).try_for_each(visit)
+    }
+
+    fn estimate(&self, word: &str) -> Vec<(String, u128)> {
+        stream_rules(word, &self.rules)
+            .map(|variant| (variant, 1))
+            .collect()
+    }
+}
+
+/// a chars stage with more stages after it estimates words with up to this
+/// many variants by listing them, so the later stages see the real variants
+pub const EXACT_ESTIMATE_LIMIT: u128 = 100_000;
+
+/// the length, count, hybrid or combine subcommand
+pub struct Chars {
+    pub command: PipelineCommand,
+    /// more stages follow this one
+    pub followed: bool,
+}
+
+impl Transform for Chars {
+    fn apply(&self, word: String, visit: &mut dyn FnMut(String) -> Result<()>) -> Result<()> {
+        match &self.command {
+            PipelineCommand::None => visit(word),
+            PipelineCommand::Length {
+                config,
+                spill: None,
+            } => config.stream(&word).try_for_each(visit),
+            PipelineCommand::Length {
+                config,
+                spill: Some(spill),
+            } => config
+                .stream_spilled(&word, spill)
+                .try_for_each(|variant| visit(variant?)),
+            PipelineCommand::Count(config) => config.stream(&word).try_for_each(visit),
+            PipelineCommand::Hybrid { prepend, append } => {
+                stream_hybrid(&word, prepend, append).try_for_each(visit)
+            }
+            PipelineCommand::Combine(combination) => combination.apply(&word, visit),
         }
     }
+
+    /// the variants themselves when later stages need them and there are few
+    /// enough, otherwise representatives
+    fn estimate(&self, word: &str) -> Vec<(String, u128)> {
+        let representatives = self.representatives(word);
+        let total = representatives
+            .iter()
+            .fold(0u128, |total, (_, count)| total.saturating_add(*count));
+        if !self.followed || total > EXACT_ESTIMATE_LIMIT {
+            return representatives;
+        }
+        let mut variants = Vec::new();
+        let listed = self.apply(word.to_owned(), &mut |variant| {
+            variants.push((variant, 1));
+            Ok(())
+        });
+        match listed {
+            Ok(()) => variants,
+            Err(_) => representatives,
+        }
+    }
+}
+
+impl Chars {
+    /// added chars are represented by the charset, cycled up to each length,
+    /// and mask positions by their first candidate
+    fn representatives(&self, word: &str) -> Vec<(String, u128)> {
+        let (chars, by_length) = match &self.command {
+            PipelineCommand::None => return vec![(word.to_owned(), 1)],
+            PipelineCommand::Length { config, .. } => (config.chars(), config.keyspace(word)),
+            PipelineCommand::Count(config) => (config.chars(), config.keyspace(word)),
+            PipelineCommand::Hybrid { prepend, append } => {
+                let (count, _) = hybrid_keyspace(word, prepend, append);
+                let first = |mask: &Mask| mask.candidates(0, 1).next().unwrap_or_default();
+                return vec![(format!("{}{word}{}", first(prepend), first(append)), count)];
+            }
+            PipelineCommand::Combine(combination) => return combination.estimate(word),
+        };
+        by_length
+            .into_iter()
+            .map(|(len, count)| {
+                let mut repr = word.to_owned();
+                for ch in chars.chars().cycle() {
+                    if repr.len() >= len {
+                        break;
+                    }
+                    repr.push(ch);
+                }
+                (repr, count)
+            })
+            .collect()
+    }
 }
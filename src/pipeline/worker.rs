@@ -1,47 +1,104 @@
 use crossbeam_channel::Sender;
+use std::sync::Arc;
+
+use super::dedup::Dedup;
+use super::scheduler::OrderWindow;
+use super::writer::WriterMessage;
 
 pub struct WorkerState {
     buffer: Vec<u8>,
-    sender: Sender<Vec<u8>>,
+    sender: Sender<WriterMessage>,
+    dedup: Option<Arc<Dedup>>,
+    /// set in ordered mode, where every buffer has to fit the window first
+    window: Option<Arc<OrderWindow>>,
+    seq: usize,
+    /// the writer is gone, so nothing more needs to be produced
+    stopped: bool,
 }
 
 impl WorkerState {
-    pub fn new(sender: Sender<Vec<u8>>, capacity: usize) -> Self {
+    pub fn new(
+        sender: Sender<WriterMessage>,
+        capacity: usize,
+        dedup: Option<Arc<Dedup>>,
+        window: Option<Arc<OrderWindow>>,
+    ) -> Self {
         Self {
             buffer: Vec::with_capacity(capacity),
             sender,
+            dedup,
+            window,
+            seq: 0,
+            stopped: false,
         }
     }
 
-    pub fn push_line(&mut self, line: &str, capacity: usize) {
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// buffer the line for the writer, false if the run already emitted it
+    pub fn push_line(&mut self, line: &str, capacity: usize) -> bool {
+        if let Some(dedup) = &self.dedup {
+            if !dedup.insert(line) {
+                return false;
+            }
+        }
         self.buffer.extend_from_slice(line.as_bytes());
         self.buffer.push(b'\n');
         if self.buffer.len() >= capacity {
             self.flush(capacity);
         }
+        true
+    }
+
+    pub fn begin_chunk(&mut self, seq: usize) {
+        self.seq = seq;
+    }
+
+    /// hand the rest of the chunk's lines to the writer and mark it complete
+    pub fn finish_chunk(&mut self, capacity: usize) {
+        self.flush(capacity);
+        if !self.stopped && self.sender.send(WriterMessage::ChunkDone(self.seq)).is_err() {
+            self.stopped = true;
+        }
     }
 
     fn flush(&mut self, capacity: usize) {
         if self.buffer.is_empty() {
             return;
         }
-        let mut out = Vec::new();
-        std::mem::swap(&mut out, &mut self.buffer);
-        if self.sender.send(out).is_ok() {
+        if self.stopped {
+            self.buffer.clear();
+            return;
+        }
+        let data = std::mem::take(&mut self.buffer);
+        let reserved = self
+            .window
+            .as_ref()
+            .is_none_or(|window| window.reserve(self.seq, data.len()).is_ok());
+        let message = WriterMessage::Data {
+            seq: self.seq,
+            data,
+        };
+        if reserved && self.sender.send(message).is_ok() {
             self.buffer = Vec::with_capacity(capacity);
         } else {
-            self.buffer.clear();
+            self.stopped = true;
         }
     }
+
 }
 
 impl Drop for WorkerState {
     fn drop(&mut self) {
-        if self.buffer.is_empty() {
+        if self.buffer.is_empty() || self.stopped {
             return;
         }
-        let mut out = Vec::new();
-        std::mem::swap(&mut out, &mut self.buffer);
-        let _ = self.sender.send(out);
+        let data = std::mem::take(&mut self.buffer);
+        let _ = self.sender.send(WriterMessage::Data {
+            seq: self.seq,
+            data,
+        });
     }
 }
@@ -0,0 +1,173 @@
+use anyhow::Result;
+use crossbeam_channel::Receiver;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::checkpoint::Checkpoint;
+use super::chunks::Pending;
+use super::compress::{Compressor, Encoder};
+use super::scheduler::OrderWindow;
+use super::split::{ShardWriter, SplitConfig};
+
+pub enum WriterMessage {
+    Data { seq: usize, data: Vec<u8> },
+    /// every line of the chunk has been sent
+    ChunkDone(usize),
+}
+
+/// what the writer tracks besides the bytes themselves
+pub struct WriterProgress {
+    pub checkpoint: Option<Checkpoint>,
+    pub checkpoint_interval: Duration,
+    /// input chunk behind every sequence number
+    pub chunks: Pending,
+    /// set in ordered mode
+    pub window: Option<Arc<OrderWindow>>,
+}
+
+pub type Destination = Encoder<Box<dyn Write + Send>>;
+
+pub fn create_writer(
+    path: Option<&str>,
+    compressor: Option<Arc<Compressor>>,
+) -> Result<Destination> {
+    let writer: Box<dyn Write + Send> = if let Some(path) = path {
+        let file = File::create(path)?;
+        Box::new(BufWriter::new(file))
+    } else {
+        Box::new(BufWriter::new(io::stdout()))
+    };
+    Ok(Encoder::new(writer, compressor))
+}
+
+/// shards named after the `path` template, each compressed on its own
+pub fn split_writer(
+    path: &str,
+    split: SplitConfig,
+    compressor: Option<Arc<Compressor>>,
+) -> Result<Destination> {
+    let template = split.template(path)?;
+    let shards = ShardWriter::new(template, split, compressor);
+    Ok(Encoder::new(Box::new(shards), None))
+}
+
+/// reopen a partial output, dropping whatever was written after the checkpoint
+pub fn resume_writer(
+    path: &str,
+    checkpoint: &Checkpoint,
+    compressor: Option<Arc<Compressor>>,
+) -> Result<Destination> {
+    let file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(checkpoint.output_bytes())?;
+    let file = OpenOptions::new().append(true).open(path)?;
+    Ok(Encoder::new(Box::new(BufWriter::new(file)), compressor))
+}
+
+pub fn spawn_writer(
+    writer: Destination,
+    receiver: Receiver<WriterMessage>,
+    progress: WriterProgress,
+) -> thread::JoinHandle<Result<()>> {
+    thread::spawn(move || {
+        let window = progress.window.clone();
+        let mut output = Output::new(writer, progress);
+        let result = receiver.iter().try_for_each(|message| match message {
+            WriterMessage::Data { seq, data } => output.data(seq, data),
+            WriterMessage::ChunkDone(seq) => output.chunk_done(seq),
+        });
+        // on an error, workers waiting on the window would otherwise wait forever
+        if let Some(window) = window {
+            window.close();
+        }
+        result.and_then(|_| output.finish())
+    })
+}
+
+struct Output {
+    writer: Destination,
+    /// output length the run resumed at
+    resumed_at: u64,
+    progress: WriterProgress,
+    last_save: Instant,
+    /// ordered mode: next sequence number to write, and what arrived early
+    next: usize,
+    held: BTreeMap<usize, Vec<Vec<u8>>>,
+    finished: BTreeSet<usize>,
+}
+
+impl Output {
+    fn new(writer: Destination, progress: WriterProgress) -> Self {
+        Self {
+            writer,
+            resumed_at: progress
+                .checkpoint
+                .as_ref()
+                .map_or(0, Checkpoint::output_bytes),
+            progress,
+            last_save: Instant::now(),
+            next: 0,
+            held: BTreeMap::new(),
+            finished: BTreeSet::new(),
+        }
+    }
+
+    fn data(&mut self, seq: usize, data: Vec<u8>) -> Result<()> {
+        if self.progress.window.is_some() && seq != self.next {
+            self.held.entry(seq).or_default().push(data);
+            return Ok(());
+        }
+        self.write(&data)
+    }
+
+    fn chunk_done(&mut self, seq: usize) -> Result<()> {
+        if self.progress.window.is_none() {
+            return self.complete(seq);
+        }
+        self.finished.insert(seq);
+        while self.finished.remove(&self.next) {
+            self.complete(self.next)?;
+            self.next += 1;
+            for data in self.held.remove(&self.next).unwrap_or_default() {
+                self.write(&data)?;
+            }
+        }
+        if let Some(window) = &self.progress.window {
+            window.advance(self.next);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        if let Some(window) = &self.progress.window {
+            window.release(data.len());
+        }
+        Ok(())
+    }
+
+    fn complete(&mut self, seq: usize) -> Result<()> {
+        let Some(checkpoint) = self.progress.checkpoint.as_mut() else {
+            return Ok(());
+        };
+        let chunk = self.progress.chunks.chunk(seq).expect("writer got an unknown chunk");
+        checkpoint.mark_done(chunk);
+        if self.last_save.elapsed() >= self.progress.checkpoint_interval {
+            self.writer.flush()?;
+            checkpoint.save(self.resumed_at + self.writer.written())?;
+            self.last_save = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        if let Some(checkpoint) = self.progress.checkpoint.as_mut() {
+            checkpoint.save(self.resumed_at + self.writer.written())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+use crate::error::{Error, Result};
+use log::warn;
+use std::fs;
+use std::path::Path;
+
+/// one line of a hashcat `.rule` file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    ops: Vec<Op>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Noop,
+    Lower,
+    Upper,
+    Capitalize,
+    InvertCapitalize,
+    ToggleAll,
+    Toggle(usize),
+    Reverse,
+    Duplicate,
+    DuplicateTimes(usize),
+    Reflect,
+    RotateLeft,
+    RotateRight,
+    Append(char),
+    Prepend(char),
+    TruncateLeft,
+    TruncateRight,
+    Delete(usize),
+    Extract(usize, usize),
+    Omit(usize, usize),
+    Insert(usize, char),
+    Overwrite(usize, char),
+    TruncateAt(usize),
+    Replace(char, char),
+    Purge(char),
+    DuplicateFirst(usize),
+    DuplicateLast(usize),
+    DuplicateEach,
+    SwapFront,
+    SwapBack,
+    Swap(usize, usize),
+    ShiftLeft(usize),
+    ShiftRight(usize),
+    Increment(usize),
+    Decrement(usize),
+    ReplaceWithNext(usize),
+    ReplaceWithPrev(usize),
+    DuplicateBlockFront(usize),
+    DuplicateBlockBack(usize),
+    Title,
+    TitleSeparator(char),
+    ToggleAfter(usize, char),
+    Memorize,
+    AppendMemory,
+    PrependMemory,
+    InsertMemory(usize, usize, usize),
+    RejectLonger(usize),
+    RejectShorter(usize),
+    RejectLengthNot(usize),
+    RejectContains(char),
+    RejectMissing(char),
+    RejectFirstNot(char),
+    RejectLastNot(char),
+    RejectAtNot(usize, char),
+    RejectFewer(usize, char),
+    RejectEqualsMemory,
+}
+
+/// load every rule of a hashcat rule file. invalid or unsupported rules are
+/// skipped with a warning, like hashcat does
+pub fn load_rules(path: impl AsRef<Path>) -> Result<Vec<Rule>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let mut rules = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_rule(line) {
+            Ok(rule) => rules.push(rule),
+            Err(err) => warn!(
+                "skipping rule on line {} of {}: {}",
+                idx + 1,
+                path.display(),
+                err
+            ),
+        }
+    }
+    if rules.is_empty() {
+        return Err(Error::NoRules(path.to_path_buf()));
+    }
+    Ok(rules)
+}
+
+pub fn parse_rule(line: &str) -> Result<Rule> {
+    parse_ops(line).map(|ops| Rule { ops }).map_err(Error::Rule)
+}
+
+fn parse_ops(line: &str) -> Result<Vec<Op>, String> {
+    let mut chars = line.chars();
+    let mut ops = Vec::new();
+    while let Some(function) = chars.next() {
+        let mut arg = || {
+            chars
+                .next()
+                .ok_or_else(|| format!("'{function}' is missing an argument"))
+        };
+        let op = match function {
+            ' ' | '\t' => continue,
+            ':' => Op::Noop,
+            'l' => Op::Lower,
+            'u' => Op::Upper,
+            'c' => Op::Capitalize,
+            'C' => Op::InvertCapitalize,
+            't' => Op::ToggleAll,
+            'T' => Op::Toggle(position(arg()?)?),
+            'r' => Op::Reverse,
+            'd' => Op::Duplicate,
+            'p' => Op::DuplicateTimes(position(arg()?)?),
+            'f' => Op::Reflect,
+            '{' => Op::RotateLeft,
+            '}' => Op::RotateRight,
+            '$' => Op::Append(arg()?),
+            '^' => Op::Prepend(arg()?),
+            '[' => Op::TruncateLeft,
+            ']' => Op::TruncateRight,
+            'D' => Op::Delete(position(arg()?)?),
+            'x' => Op::Extract(position(arg()?)?, position(arg()?)?),
+            'O' => Op::Omit(position(arg()?)?, position(arg()?)?),
+            'i' => Op::Insert(position(arg()?)?, arg()?),
+            'o' => Op::Overwrite(position(arg()?)?, arg()?),
+            '\'' => Op::TruncateAt(position(arg()?)?),
+            's' => Op::Replace(arg()?, arg()?),
+            '@' => Op::Purge(arg()?),
+            'z' => Op::DuplicateFirst(position(arg()?)?),
+            'Z' => Op::DuplicateLast(position(arg()?)?),
+            'q' => Op::DuplicateEach,
+            'k' => Op::SwapFront,
+            'K' => Op::SwapBack,
+            '*' => Op::Swap(position(arg()?)?, position(arg()?)?),
+            'L' => Op::ShiftLeft(position(arg()?)?),
+            'R' => Op::ShiftRight(position(arg()?)?),
+            '+' => Op::Increment(position(arg()?)?),
+            '-' => Op::Decrement(position(arg()?)?),
+            '.' => Op::ReplaceWithNext(position(arg()?)?),
+            ',' => Op::ReplaceWithPrev(position(arg()?)?),
+            'y' => Op::DuplicateBlockFront(position(arg()?)?),
+            'Y' => Op::DuplicateBlockBack(position(arg()?)?),
+            'E' => Op::Title,
+            'e' => Op::TitleSeparator(arg()?),
+            '3' => Op::ToggleAfter(position(arg()?)?, arg()?),
+            'M' => Op::Memorize,
+            '4' => Op::AppendMemory,
+            '6' => Op::PrependMemory,
+            'X' => Op::InsertMemory(position(arg()?)?, position(arg()?)?, position(arg()?)?),
+            '<' => Op::RejectLonger(position(arg()?)?),
+            '>' => Op::RejectShorter(position(arg()?)?),
+            '_' => Op::RejectLengthNot(position(arg()?)?),
+            '!' => Op::RejectContains(arg()?),
+            '/' => Op::RejectMissing(arg()?),
+            '(' => Op::RejectFirstNot(arg()?),
+            ')' => Op::RejectLastNot(arg()?),
+            '=' => Op::RejectAtNot(position(arg()?)?, arg()?),
+            '%' => Op::RejectFewer(position(arg()?)?, arg()?),
+            'Q' => Op::RejectEqualsMemory,
+            other => return Err(format!("unsupported rule function '{other}'")),
+        };
+        ops.push(op);
+    }
+    Ok(ops)
+}
+
+/// hashcat positions: 0-9 then A-Z for 10-35
+fn position(ch: char) -> Result<usize, String> {
+    match ch {
+        '0'..='9' => Ok(ch as usize - '0' as usize),
+        'A'..='Z' => Ok(ch as usize - 'A' as usize + 10),
+        _ => Err(format!("invalid position '{ch}'")),
+    }
+}
+
+impl Rule {
+    /// apply the rule to `word`, or `None` when one of its reject functions fires
+    pub fn apply(&self, word: &str) -> Option<String> {
+        let mut out: Vec<char> = word.chars().collect();
+        let mut memory = out.clone();
+        for op in &self.ops {
+            apply_op(*op, &mut out, &mut memory)?;
+        }
+        Some(out.into_iter().collect())
+    }
+}
+
+/// gen the result of every rule applied to the word, skipping rejected ones
+pub fn stream_rules<'a>(word: &str, rules: &'a [Rule]) -> impl Iterator<Item = String> + 'a {
+    let word = word.to_string();
+    rules.iter().filter_map(move |rule| rule.apply(&word))
+}
+
+fn apply_op(op: Op, out: &mut Vec<char>, memory: &mut Vec<char>) -> Option<()> {
+    let len = out.len();
+    match op {
+        Op::Noop => {}
+        Op::Lower => out.iter_mut().for_each(|c| *c = c.to_ascii_lowercase()),
+        Op::Upper => out.iter_mut().for_each(|c| *c = c.to_ascii_uppercase()),
+        Op::Capitalize => {
+            out.iter_mut().for_each(|c| *c = c.to_ascii_lowercase());
+            if let Some(first) = out.first_mut() {
+                *first = first.to_ascii_uppercase();
+            }
+        }
+        Op::InvertCapitalize => {
+            out.iter_mut().for_each(|c| *c = c.to_ascii_uppercase());
+            if let Some(first) = out.first_mut() {
+                *first = first.to_ascii_lowercase();
+            }
+        }
+        Op::ToggleAll => out.iter_mut().for_each(|c| *c = toggle(*c)),
+        Op::Toggle(pos) => {
+            if let Some(c) = out.get_mut(pos) {
+                *c = toggle(*c);
+            }
+        }
+        Op::Reverse => out.reverse(),
+        Op::Duplicate => out.extend_from_within(..),
+        Op::DuplicateTimes(times) => {
+            let word = out.clone();
+            for _ in 0..times {
+                out.extend_from_slice(&word);
+            }
+        }
+        Op::Reflect => {
+            let reversed: Vec<char> = out.iter().rev().copied().collect();
+            out.extend(reversed);
+        }
+        Op::RotateLeft => {
+            if len > 0 {
+                out.rotate_left(1);
+            }
+        }
+        Op::RotateRight => {
+            if len > 0 {
+                out.rotate_right(1);
+            }
+        }
+        Op::Append(ch) => out.push(ch),
+        Op::Prepend(ch) => out.insert(0, ch),
+        Op::TruncateLeft => {
+            if len > 0 {
+                out.remove(0);
+            }
+        }
+        Op::TruncateRight => {
+            out.pop();
+        }
+        Op::Delete(pos) => {
+            if pos < len {
+                out.remove(pos);
+            }
+        }
+        Op::Extract(pos, count) => {
+            if pos + count <= len {
+                out.truncate(pos + count);
+                out.drain(..pos);
+            }
+        }
+        Op::Omit(pos, count) => {
+            if pos + count <= len {
+                out.drain(pos..pos + count);
+            }
+        }
+        Op::Insert(pos, ch) => {
+            if pos <= len {
+                out.insert(pos, ch);
+            }
+        }
+        Op::Overwrite(pos, ch) => {
+            if let Some(c) = out.get_mut(pos) {
+                *c = ch;
+            }
+        }
+        Op::TruncateAt(pos) => out.truncate(pos),
+        Op::Replace(from, to) => out.iter_mut().filter(|c| **c == from).for_each(|c| *c = to),
+        Op::Purge(ch) => out.retain(|c| *c != ch),
+        Op::DuplicateFirst(times) => {
+            if let Some(&first) = out.first() {
+                out.splice(0..0, std::iter::repeat_n(first, times));
+            }
+        }
+        Op::DuplicateLast(times) => {
+            if let Some(&last) = out.last() {
+                out.extend(std::iter::repeat_n(last, times));
+            }
+        }
+        Op::DuplicateEach => *out = out.iter().flat_map(|&c| [c, c]).collect(),
+        Op::SwapFront => {
+            if len >= 2 {
+                out.swap(0, 1);
+            }
+        }
+        Op::SwapBack => {
+            if len >= 2 {
+                out.swap(len - 1, len - 2);
+            }
+        }
+        Op::Swap(a, b) => {
+            if a < len && b < len {
+                out.swap(a, b);
+            }
+        }
+        Op::ShiftLeft(pos) => map_byte(out, pos, |b| b << 1),
+        Op::ShiftRight(pos) => map_byte(out, pos, |b| b >> 1),
+        Op::Increment(pos) => map_byte(out, pos, |b| b.wrapping_add(1)),
+        Op::Decrement(pos) => map_byte(out, pos, |b| b.wrapping_sub(1)),
+        Op::ReplaceWithNext(pos) => {
+            if pos + 1 < len {
+                out[pos] = out[pos + 1];
+            }
+        }
+        Op::ReplaceWithPrev(pos) => {
+            if pos > 0 && pos < len {
+                out[pos] = out[pos - 1];
+            }
+        }
+        Op::DuplicateBlockFront(count) => {
+            if count <= len {
+                let block: Vec<char> = out[..count].to_vec();
+                out.splice(0..0, block);
+            }
+        }
+        Op::DuplicateBlockBack(count) => {
+            if count <= len {
+                out.extend_from_within(len - count..);
+            }
+        }
+        Op::Title => title_case(out, ' '),
+        Op::TitleSeparator(separator) => title_case(out, separator),
+        Op::ToggleAfter(nth, ch) => {
+            let found = out
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == ch)
+                .nth(nth)
+                .map(|(idx, _)| idx);
+            if let Some(c) = found.and_then(|idx| out.get_mut(idx + 1)) {
+                *c = toggle(*c);
+            }
+        }
+        Op::Memorize => *memory = out.clone(),
+        Op::AppendMemory => out.extend_from_slice(memory),
+        Op::PrependMemory => {
+            out.splice(0..0, memory.iter().copied());
+        }
+        Op::InsertMemory(from, count, at) => {
+            if from + count <= memory.len() && at <= len {
+                out.splice(at..at, memory[from..from + count].iter().copied());
+            }
+        }
+        Op::RejectLonger(limit) => reject_if(len > limit)?,
+        Op::RejectShorter(limit) => reject_if(len < limit)?,
+        Op::RejectLengthNot(target) => reject_if(len != target)?,
+        Op::RejectContains(ch) => reject_if(out.contains(&ch))?,
+        Op::RejectMissing(ch) => reject_if(!out.contains(&ch))?,
+        Op::RejectFirstNot(ch) => reject_if(out.first() != Some(&ch))?,
+        Op::RejectLastNot(ch) => reject_if(out.last() != Some(&ch))?,
+        Op::RejectAtNot(pos, ch) => reject_if(out.get(pos) != Some(&ch))?,
+        Op::RejectFewer(count, ch) => reject_if(out.iter().filter(|c| **c == ch).count() < count)?,
+        Op::RejectEqualsMemory => reject_if(*out == *memory)?,
+    }
+    Some(())
+}
+
+fn reject_if(reject: bool) -> Option<()> {
+    (!reject).then_some(())
+}
+
+fn toggle(ch: char) -> char {
+    if ch.is_ascii_lowercase() {
+        ch.to_ascii_uppercase()
+    } else {
+        ch.to_ascii_lowercase()
+    }
+}
+
+/// byte arithmetic only makes sense on ascii, other chars are left alone
+fn map_byte(out: &mut [char], pos: usize, f: impl Fn(u8) -> u8) {
+    if let Some(c) = out.get_mut(pos) {
+        if c.is_ascii() {
+            *c = char::from(f(*c as u8) & 0x7f);
+        }
+    }
+}
+
+fn title_case(out: &mut [char], separator: char) {
+    let mut start = true;
+    for c in out.iter_mut() {
+        *c = if start {
+            c.to_ascii_uppercase()
+        } else {
+            c.to_ascii_lowercase()
+        };
+        start = *c == separator;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(rule: &str, word: &str) -> Option<String> {
+        parse_rule(rule).unwrap().apply(word)
+    }
+
+    #[test]
+    fn applies_common_rules() {
+        assert_eq!(apply(":", "pass").as_deref(), Some("pass"));
+        assert_eq!(apply("c", "pASS").as_deref(), Some("Pass"));
+        assert_eq!(apply("u $1 $2", "pass").as_deref(), Some("PASS12"));
+        assert_eq!(apply("^1 r", "pass").as_deref(), Some("ssap1"));
+        assert_eq!(apply("sa@ T0", "pass").as_deref(), Some("P@ss"));
+        assert_eq!(apply("d ]", "ab").as_deref(), Some("aba"));
+        assert_eq!(apply("i1X o0Y", "abc").as_deref(), Some("YXbc"));
+        assert_eq!(apply("DA", "abc").as_deref(), Some("abc"));
+        assert_eq!(apply("x12", "abcdef").as_deref(), Some("bc"));
+        assert_eq!(apply("O12", "abcdef").as_deref(), Some("adef"));
+        assert_eq!(apply("f {", "ab").as_deref(), Some("bbaa"));
+        assert_eq!(apply("E", "star wARS").as_deref(), Some("Star Wars"));
+        assert_eq!(apply("M l 4", "AB").as_deref(), Some("abAB"));
+        assert_eq!(apply("$ ", "a").as_deref(), Some("a "));
+    }
+
+    #[test]
+    fn reject_rules_drop_candidates() {
+        assert_eq!(apply("<4", "passw"), None);
+        assert_eq!(apply(">6", "pass"), None);
+        assert_eq!(apply("!s", "pass"), None);
+        assert_eq!(apply("(p )s", "pass").as_deref(), Some("pass"));
+        assert_eq!(apply("l Q", "pass"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_functions_and_missing_arguments() {
+        assert!(parse_rule("?").is_err());
+        assert!(parse_rule("$").is_err());
+        assert!(parse_rule("T!").is_err());
+    }
+}
@@ -1,59 +1,402 @@
-pub fn stream(word: &str) -> impl Iterator<Item = String> {
-    let base = collapse_spaces(&normalize_delimiters(&strip_bracketed(word)));
-    if base.is_empty() {
-        return Vec::new().into_iter();
-    }
-    let lower_base = base.to_ascii_lowercase();
-    let final_text = collapse_spaces(&trim_metadata_suffix(&lower_base));
-    let trimmed_len = if final_text.is_empty() {
-        0
-    } else {
-        final_text.split_whitespace().count()
+use crate::character_combinations::LengthUnit;
+use crate::error::{Error, Result};
+use deunicode::deunicode_with_tofu;
+use std::collections::HashSet;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::str::FromStr;
+
+/// years a title may end in when no config sets its own window
+const DEFAULT_YEARS: RangeInclusive<u16> = 1800..=2099;
+
+/// why `stream_checked` dropped a candidate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthRejection {
+    TooShort,
+    TooLong,
+}
+
+/// built-in metadata vocabularies, one per language
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataPack {
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+impl FromStr for MetadataPack {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "en" => Ok(MetadataPack::En),
+            "de" => Ok(MetadataPack::De),
+            "fr" => Ok(MetadataPack::Fr),
+            "es" => Ok(MetadataPack::Es),
+            _ => Err(Error::UnknownName {
+                kind: "metadata pack",
+                name: name.to_string(),
+                expected: "en, de, fr or es",
+            }),
+        }
+    }
+}
+
+impl MetadataPack {
+    fn entries(self) -> &'static str {
+        match self {
+            MetadataPack::En => {
+                "words=film,films,movie,movies,episode,episodes,series,season,seasons,show,shows,\
+                 novel,novels,game,games,album,albums,song,songs,soundtrack\n\
+                 tags=remastered,remaster,deluxe,edition,extended,expanded,uncut,unrated,ost"
+            }
+            MetadataPack::De => {
+                "words=film,filme,fernsehfilm,serie,serien,fernsehserie,staffel,staffeln,folge,\
+                 folgen,episode,episoden,roman,romane,spiel,spiele,album,alben,lied,lieder,\
+                 soundtrack\n\
+                 tags=edition,ausgabe,neuauflage,fassung,ungekürzt,remastered,ost"
+            }
+            MetadataPack::Fr => {
+                "words=film,films,téléfilm,série,séries,feuilleton,saison,saisons,épisode,\
+                 épisodes,émission,roman,romans,jeu,jeux,album,albums,chanson,chansons\n\
+                 tags=édition,remasterisé,remasterisée,intégrale,vo,vf,bo"
+            }
+            MetadataPack::Es => {
+                "words=película,películas,serie,series,telenovela,temporada,temporadas,\
+                 episodio,episodios,novela,novelas,juego,juegos,álbum,álbumes,canción,\
+                 canciones\n\
+                 tags=edición,remasterizado,remasterizada,bso"
+            }
+        }
+    }
+}
+
+/// what `stream` trims off the end of a title. `words` only go after a
+/// trailing year (`heat 1995 film`), `tags` go wherever the title ends in them
+/// (`thriller remastered`)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    words: HashSet<String>,
+    tags: HashSet<String>,
+    /// four-digit years that start the suffix, 1800-2099 when unset
+    years: Option<RangeInclusive<u16>>,
+}
+
+impl Metadata {
+    pub fn pack(pack: MetadataPack) -> Self {
+        Self::parse(pack.entries()).expect("built-in metadata packs are valid")
+    }
+
+    /// read a config file of `words=film,season`, `tags=ost,deluxe` and
+    /// `years=1900-2030` lines. `#` starts a comment line
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text).map_err(|err| match err {
+            Error::Metadata(message) => Error::Metadata(format!("{}: {message}", path.display())),
+            other => other,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut metadata = Metadata::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, values) = line.split_once('=').ok_or_else(|| {
+                Error::Metadata(format!("line {}: expected `key=value,value`", idx + 1))
+            })?;
+            let values = values
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_lowercase);
+            match key.trim() {
+                "words" => metadata.words.extend(values),
+                "tags" => metadata.tags.extend(values),
+                "years" => metadata.years = Some(parse_years(values.collect(), idx + 1)?),
+                other => {
+                    return Err(Error::Metadata(format!(
+                        "line {}: unknown key '{other}' (expected words, tags or years)",
+                        idx + 1
+                    )))
+                }
+            }
+        }
+        Ok(metadata)
+    }
+
+    /// adds the words and tags of `other`, and its year window if it sets one
+    pub fn extend(&mut self, other: Metadata) {
+        self.words.extend(other.words);
+        self.tags.extend(other.tags);
+        if other.years.is_some() {
+            self.years = other.years;
+        }
+    }
+
+    fn is_word(&self, token: &str) -> bool {
+        self.words.contains(&trim_token(token).to_lowercase())
+    }
+
+    fn is_tag(&self, token: &str) -> bool {
+        self.tags.contains(&trim_token(token).to_lowercase())
+    }
+
+    fn is_year(&self, token: &str) -> bool {
+        let years = self.years.as_ref().unwrap_or(&DEFAULT_YEARS);
+        token.len() == 4
+            && token.chars().all(|c| c.is_ascii_digit())
+            && matches!(token.parse::<u16>(), Ok(year) if years.contains(&year))
+    }
+}
+
+fn parse_years(values: Vec<String>, line: usize) -> Result<RangeInclusive<u16>> {
+    let invalid = || Error::Metadata(format!("line {line}: expected `years=1900-2030`"));
+    let [range] = values.as_slice() else {
+        return Err(invalid());
     };
-    let mut original_tokens: Vec<&str> = base.split_whitespace().collect();
-    original_tokens.truncate(trimmed_len);
-    let trimmed_original = original_tokens.join(" ");
-    let orig = trimmed_original.trim().to_string();
-    let clean: String = final_text
-        .chars()
-        .filter(|c| c.is_ascii_alphanumeric())
-        .collect();
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+    let start: u16 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u16 = end.trim().parse().map_err(|_| invalid())?;
+    if !(1000..=end).contains(&start) || end > 9999 {
+        return Err(invalid());
+    }
+    Ok(start..=end)
+}
 
-    let mut out = Vec::new();
-    if meets_length(&clean) {
-        out.push(clean);
+/// how `stream` cleans a line up
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SanitizeConfig {
+    transliterate: bool,
+    keep_unicode: bool,
+    year_variants: bool,
+    metadata: Metadata,
+    min_len: usize,
+    max_len: usize,
+    unit: LengthUnit,
+}
+
+impl Default for SanitizeConfig {
+    fn default() -> Self {
+        Self {
+            transliterate: true,
+            keep_unicode: false,
+            year_variants: false,
+            metadata: Metadata::pack(MetadataPack::En),
+            min_len: 2,
+            max_len: 64,
+            unit: LengthUnit::default(),
+        }
+    }
+}
+
+impl SanitizeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// fold diacritics and spell other scripts in Latin (`é`->`e`, `ß`->`ss`,
+    /// `Ж`->`zh`) before dropping non-ASCII chars from the squashed form
+    pub fn transliterate(mut self, transliterate: bool) -> Self {
+        self.transliterate = transliterate;
+        self
+    }
+
+    /// also emit the squashed form in its own script, lowercased (`amélie`)
+    pub fn keep_unicode(mut self, keep_unicode: bool) -> Self {
+        self.keep_unicode = keep_unicode;
+        self
+    }
+
+    /// when a title ends in a release year, also emit the squashed forms
+    /// followed by it: `titanic1997`, `titanic97`, `titanic_1997`,
+    /// `titanic-1997` and `titanic.1997`
+    pub fn year_variants(mut self, year_variants: bool) -> Self {
+        self.year_variants = year_variants;
+        self
+    }
+
+    /// the vocabulary and year window trimmed off the end of titles,
+    /// the `en` pack by default
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// shortest candidate kept, in `unit`s. 2 by default, and never below 1
+    pub fn min_len(mut self, min_len: usize) -> Self {
+        self.min_len = min_len.max(1);
+        self
+    }
+
+    /// longest candidate kept, in `unit`s. 64 by default
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// what the length bounds count, chars by default
+    pub fn unit(mut self, unit: LengthUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// the release year the title ends in, the one `stream` trims off
+    pub fn release_year(&self, word: &str) -> Option<u16> {
+        self.title(word).and_then(|(_, _, year)| year)
+    }
+
+    fn check_length(&self, candidate: String) -> Result<String, LengthRejection> {
+        let len = self.unit.measure(&candidate);
+        if len < self.min_len {
+            Err(LengthRejection::TooShort)
+        } else if len > self.max_len {
+            Err(LengthRejection::TooLong)
+        } else {
+            Ok(candidate)
+        }
+    }
+
+    /// the line with brackets and delimiters cleaned up, the same lowercased
+    /// and trimmed of its metadata suffix, and the year that suffix started
+    /// with or, failing that, the first year in brackets (`titanic (1997 film)`)
+    fn title(&self, word: &str) -> Option<(String, String, Option<u16>)> {
+        let (outside, bracketed) = strip_bracketed(word);
+        let base = collapse_spaces(&normalize_delimiters(&outside));
+        if base.is_empty() {
+            return None;
+        }
+        let lower_base = base.to_ascii_lowercase();
+        let (trimmed, year) = trim_metadata_suffix(&lower_base, &self.metadata);
+        let year = year.or_else(|| {
+            normalize_delimiters(&bracketed)
+                .split_whitespace()
+                .map(trim_token)
+                .find(|token| self.metadata.is_year(token))
+                .and_then(|token| token.parse().ok())
+        });
+        Some((base, collapse_spaces(&trimmed), year))
+    }
+
+    pub fn stream(&self, word: &str) -> impl Iterator<Item = String> {
+        let (_, candidates) = self.stream_checked(word);
+        candidates.filter_map(|candidate| candidate.ok())
     }
-    if orig != out.last().map_or("", String::as_str) && meets_length(&orig) {
-        out.push(orig);
+
+    /// the release year found while trimming, as `release_year` gives it, and
+    /// every distinct candidate, with the ones outside the length bounds as
+    /// the reason they are dropped. forms that come out empty are left out
+    pub fn stream_checked(
+        &self,
+        word: &str,
+    ) -> (
+        Option<u16>,
+        impl Iterator<Item = Result<String, LengthRejection>>,
+    ) {
+        let Some((base, final_text, year)) = self.title(word) else {
+            return (None, Vec::new().into_iter());
+        };
+        let trimmed_len = if final_text.is_empty() {
+            0
+        } else {
+            final_text.split_whitespace().count()
+        };
+        let mut original_tokens: Vec<&str> = base.split_whitespace().collect();
+        original_tokens.truncate(trimmed_len);
+        let trimmed_original = original_tokens.join(" ");
+        let orig = trimmed_original.trim().to_string();
+        let folded = if self.transliterate {
+            // chars without a Latin spelling are dropped
+            deunicode_with_tofu(&final_text, "").to_ascii_lowercase()
+        } else {
+            final_text.clone()
+        };
+        let clean: String = folded
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let native: Option<String> = self.keep_unicode.then(|| {
+            final_text
+                .to_lowercase()
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect()
+        });
+
+        let dated: Vec<String> = match year.filter(|_| self.year_variants) {
+            Some(year) => [Some(&clean), native.as_ref()]
+                .into_iter()
+                .flatten()
+                .filter(|squashed| !squashed.is_empty())
+                .flat_map(|squashed| {
+                    [
+                        format!("{squashed}{year}"),
+                        format!("{squashed}{:02}", year % 100),
+                        format!("{squashed}_{year}"),
+                        format!("{squashed}-{year}"),
+                        format!("{squashed}.{year}"),
+                    ]
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut distinct: Vec<String> = Vec::new();
+        for candidate in [Some(clean), native, Some(orig)]
+            .into_iter()
+            .flatten()
+            .chain(dated)
+        {
+            if !candidate.is_empty() && !distinct.contains(&candidate) {
+                distinct.push(candidate);
+            }
+        }
+        let out: Vec<Result<String, LengthRejection>> = distinct
+            .into_iter()
+            .map(|candidate| self.check_length(candidate))
+            .collect();
+        (year, out.into_iter())
     }
-    out.into_iter()
 }
 
-fn meets_length(value: &str) -> bool {
-    let len = value.len();
-    len >= 2 && len <= 64
+pub fn stream(word: &str) -> impl Iterator<Item = String> {
+    SanitizeConfig::default().stream(word)
 }
 
-fn strip_bracketed(input: &str) -> String {
+/// the text outside brackets, and the text inside them
+fn strip_bracketed(input: &str) -> (String, String) {
     let mut out = String::with_capacity(input.len());
+    let mut inside = String::new();
     let mut depth = 0usize;
     for ch in input.chars() {
         match ch {
             '(' | '[' | '{' => {
                 depth += 1;
+                inside.push(' ');
                 continue;
             }
             ')' | ']' | '}' if depth > 0 => {
                 depth -= 1;
+                inside.push(' ');
                 continue;
             }
             _ => {}
         }
         if depth == 0 {
             out.push(ch);
+        } else {
+            inside.push(ch);
         }
     }
-    out
+    (out, inside)
 }
 
 fn normalize_delimiters(input: &str) -> String {
@@ -71,33 +414,35 @@ fn collapse_spaces(input: &str) -> String {
     input.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-fn trim_metadata_suffix(text: &str) -> String {
+fn trim_metadata_suffix(text: &str, metadata: &Metadata) -> (String, Option<u16>) {
     let mut tokens: Vec<&str> = text.split_whitespace().collect();
-    while tokens
-        .last()
-        .map_or(false, |t| t.chars().all(|c| !c.is_ascii_alphanumeric()))
-    {
+    // a title that is nothing but tags is left alone
+    while tokens.last().is_some_and(|t| {
+        t.chars().all(|c| !c.is_alphanumeric()) || (tokens.len() > 1 && metadata.is_tag(t))
+    }) {
         tokens.pop();
     }
-    if let Some(cut) = locate_year_cut(&tokens) {
+    let mut year = None;
+    if let Some(cut) = locate_year_cut(&tokens, metadata) {
+        year = trim_token(tokens[cut]).parse().ok();
         tokens.truncate(cut);
-        while tokens.last().map_or(false, |t| is_metadata_word(trim_token(t))) {
+        while tokens.last().is_some_and(|t| metadata.is_word(t) || metadata.is_tag(t)) {
             tokens.pop();
         }
     }
-    tokens.join(" ")
+    (tokens.join(" "), year)
 }
 
-fn locate_year_cut(tokens: &[&str]) -> Option<usize> {
+fn locate_year_cut(tokens: &[&str], metadata: &Metadata) -> Option<usize> {
     for (idx, token) in tokens.iter().enumerate().rev() {
         let trimmed = trim_token(token);
         if trimmed.is_empty() {
             continue;
         }
-        if is_year(trimmed)
+        if metadata.is_year(trimmed)
             && tokens[..idx]
                 .iter()
-                .any(|t| t.chars().any(|c| c.is_ascii_alphabetic()))
+                .any(|t| t.chars().any(|c| c.is_alphabetic()))
         {
             return Some(idx);
         }
@@ -109,17 +454,96 @@ fn trim_token(token: &str) -> &str {
     token.trim_matches(|c: char| matches!(c, ',' | '.' | '!' | '?' | '/'))
 }
 
-fn is_year(token: &str) -> bool {
-    token.len() == 4
-        && token.chars().all(|c| c.is_ascii_digit())
-        && matches!(token.parse::<u16>(), Ok(year) if (1800..=2099).contains(&year))
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_other_scripts_before_squashing() {
+        let words: Vec<String> = stream("Amélie (2001 film)").collect();
+        assert_eq!(words, vec!["amelie", "Amélie"]);
+        let words: Vec<String> = stream("Москва").collect();
+        assert_eq!(words, vec!["moskva", "Москва"]);
+        let config = SanitizeConfig::new().keep_unicode(true);
+        let words: Vec<String> = config.stream("Straße").collect();
+        assert_eq!(words, vec!["strasse", "straße", "Straße"]);
+        let config = SanitizeConfig::new().transliterate(false);
+        let words: Vec<String> = config.stream("Pokémon").collect();
+        assert_eq!(words, vec!["pokmon", "Pokémon"]);
+    }
+
+    #[test]
+    fn trims_metadata_from_packs_and_config() {
+        let words: Vec<String> = stream("Thriller (1982) Deluxe Edition").collect();
+        assert_eq!(words, vec!["thriller", "Thriller"]);
+        let words: Vec<String> = stream("Deluxe").collect();
+        assert_eq!(words, vec!["deluxe", "Deluxe"]);
+
+        let mut metadata = Metadata::pack(MetadataPack::De);
+        metadata.extend(Metadata::parse("# ours\nyears=1900-1950\ntags=Fassung\n").unwrap());
+        let config = SanitizeConfig::new().metadata(metadata);
+        let words: Vec<String> = config.stream("Metropolis Fassung").collect();
+        assert_eq!(words, vec!["metropolis", "Metropolis"]);
+        let words: Vec<String> = config.stream("Der Tunnel Film 1933").collect();
+        assert_eq!(words, vec!["dertunnel", "Der Tunnel"]);
+        let words: Vec<String> = config.stream("Das Boot 1981 Serie").collect();
+        assert_eq!(words, vec!["dasboot1981serie", "Das Boot 1981 Serie"]);
 
-fn is_metadata_word(token: &str) -> bool {
-    matches!(
-        token,
-        "film" | "films" | "movie" | "movies" | "episode" | "episodes" | "series"
-            | "season" | "seasons" | "show" | "shows" | "novel" | "novels" | "game"
-            | "games" | "album" | "albums" | "song" | "songs" | "soundtrack"
-    )
+        assert!(Metadata::parse("years=2030-1900").is_err());
+        assert!(Metadata::parse("genres=drama").is_err());
+        assert!("it".parse::<MetadataPack>().is_err());
+    }
+
+    #[test]
+    fn emits_release_year_variants() {
+        let config = SanitizeConfig::new().year_variants(true);
+        let words: Vec<String> = config.stream("Titanic (1997 film)").collect();
+        assert_eq!(
+            words,
+            vec![
+                "titanic",
+                "Titanic",
+                "titanic1997",
+                "titanic97",
+                "titanic_1997",
+                "titanic-1997",
+                "titanic.1997"
+            ]
+        );
+        let words: Vec<String> = config.stream("The Matrix 1999 film").collect();
+        assert_eq!(
+            words,
+            vec![
+                "thematrix",
+                "The Matrix",
+                "thematrix1999",
+                "thematrix99",
+                "thematrix_1999",
+                "thematrix-1999",
+                "thematrix.1999"
+            ]
+        );
+        assert_eq!(config.release_year("Heat 1995"), Some(1995));
+        assert_eq!(config.release_year("1984"), None);
+        assert_eq!(config.stream_checked("Heat 1995").0, Some(1995));
+        let words: Vec<String> = stream("Heat 1995").collect();
+        assert_eq!(words, vec!["heat", "Heat"]);
+    }
+
+    #[test]
+    fn reports_candidates_outside_the_length_bounds() {
+        let words: Vec<String> = stream("Up").collect();
+        assert_eq!(words, vec!["up", "Up"]);
+        let words: Vec<String> = stream("W").collect();
+        assert!(words.is_empty());
+        let config = SanitizeConfig::new().min_len(1).max_len(5);
+        let words: Vec<String> = config.stream("W").collect();
+        assert_eq!(words, vec!["w", "W"]);
+        let (year, checked) = config.stream_checked("Jaws 2");
+        assert_eq!(year, None);
+        assert_eq!(
+            checked.collect::<Vec<_>>(),
+            vec![Ok("jaws2".to_string()), Err(LengthRejection::TooLong)]
+        );
+    }
 }
@@ -0,0 +1,82 @@
+use std::fs;
+use std::io::Write;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn cemplox(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cemplox"));
+    command.args(["--log-level", "error"]).args(args);
+    command
+}
+
+#[test]
+fn resumed_run_matches_an_uninterrupted_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("words.txt");
+    let mut file = fs::File::create(&input).unwrap();
+    for n in 0..20_000 {
+        writeln!(file, "word{n}").unwrap();
+    }
+    let input = input.to_str().unwrap();
+    let full = dir.path().join("full.txt");
+    let out = dir.path().join("out.txt");
+    let ckpt = dir.path().join("run.ckpt");
+    let (full, out, ckpt) = (
+        full.to_str().unwrap(),
+        out.to_str().unwrap(),
+        ckpt.to_str().unwrap(),
+    );
+    let run = |out: &str, extra: &[&str]| {
+        let mut args = vec!["--file", input, "-o", out, "--max-threads", "4"];
+        args.extend(extra);
+        args.extend(["-C", "0123456789", "count", "--append", "2"]);
+        cemplox(&args)
+    };
+
+    let status = run(full, &["--ordered"]).status().unwrap();
+    assert!(status.success());
+
+    // stop the checkpointed run partway, leaving output past its last save
+    let mut child = run(out, &["--checkpoint", ckpt, "--checkpoint-interval", "0"])
+        .spawn()
+        .unwrap();
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none()
+        && fs::metadata(out).map_or(0, |meta| meta.len()) < 1 << 20
+    {
+        assert!(start.elapsed() < Duration::from_secs(60), "no output");
+        thread::sleep(Duration::from_millis(5));
+    }
+    let _ = child.kill();
+    child.wait().unwrap();
+
+    let status = run(out, &["--checkpoint", ckpt, "--resume"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(fs::read(out).unwrap() == fs::read(full).unwrap());
+}
+
+#[test]
+fn dry_run_leaves_the_output_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("words.txt");
+    let out = dir.path().join("out.txt");
+    fs::write(&input, "word\n").unwrap();
+    fs::write(&out, "kept\n").unwrap();
+    let (input, out) = (input.to_str().unwrap(), out.to_str().unwrap());
+    for extra in [&["-o", out][..], &["--checkpoint", "run.ckpt"][..]] {
+        let mut args = vec!["--file", input, "--dry-run"];
+        args.extend(extra);
+        args.extend(["count", "--append", "1"]);
+        let output = cemplox(&args).output().unwrap();
+        assert!(!output.status.success(), "{extra:?}");
+    }
+    assert_eq!(fs::read_to_string(out).unwrap(), "kept\n");
+    let output = cemplox(&["--file", input, "--dry-run", "count", "--append", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!output.stdout.is_empty());
+}
@@ -0,0 +1,137 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn wordlist(lines: usize) -> tempfile::NamedTempFile {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    for n in 0..lines {
+        writeln!(file, "word{n}").unwrap();
+    }
+    file
+}
+
+fn cemplox(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cemplox"));
+    command.args(["--log-level", "error"]).args(args);
+    command
+}
+
+fn ordered_run(input: &str, threads: usize) -> Output {
+    let threads = threads.to_string();
+    let args = [
+        "--file",
+        input,
+        "--ordered",
+        "--max-threads",
+        &threads,
+        "-c",
+        "-C",
+        "12",
+        "count",
+        "--append",
+        "1",
+    ];
+    let output = cemplox(&args).output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+    output
+}
+
+#[test]
+fn ordered_output_does_not_depend_on_thread_count() {
+    let input = wordlist(20_000);
+    let input = input.path().to_str().unwrap();
+    let single = ordered_run(input, 1).stdout;
+    assert!(single.starts_with(b"word0\nword01\nword02\n"));
+    for threads in [2, 4] {
+        assert!(
+            ordered_run(input, threads).stdout == single,
+            "{threads} threads"
+        );
+    }
+}
+
+#[test]
+fn ordered_run_stops_when_the_reader_goes_away() {
+    let input = wordlist(20_000);
+    let mut child = cemplox(&[
+        "--file",
+        input.path().to_str().unwrap(),
+        "--ordered",
+        "--max-threads",
+        "4",
+        "--reorder-window",
+        "2",
+        "-C",
+        "0123456789",
+        "count",
+        "--append",
+        "3",
+    ])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first)
+        .unwrap();
+    assert_eq!(first, "word0\n");
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(60) {
+            child.kill().unwrap();
+            panic!("cemplox kept running after its output was closed");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn ordered_rejects_dedup() {
+    let input = wordlist(1);
+    let output = cemplox(&[
+        "--file",
+        input.path().to_str().unwrap(),
+        "--ordered",
+        "--dedup",
+    ])
+    .output()
+    .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn streamed_run_stops_when_the_reader_goes_away() {
+    let input = wordlist(200_000);
+    let mut child = cemplox(&[
+        "--file",
+        "-",
+        "--ordered",
+        "--max-threads",
+        "2",
+        "-C",
+        "0123456789",
+        "count",
+        "--append",
+        "2",
+    ])
+    .stdin(std::fs::File::open(input.path()).unwrap())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::null())
+    .spawn()
+    .unwrap();
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first)
+        .unwrap();
+    assert_eq!(first, "word0\n");
+    let start = Instant::now();
+    while child.try_wait().unwrap().is_none() {
+        if start.elapsed() > Duration::from_secs(60) {
+            child.kill().unwrap();
+            panic!("cemplox kept running after its output was closed");
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}